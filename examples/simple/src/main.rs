@@ -1,49 +1,253 @@
 #![no_std]
 #![no_main]
 
-use core::sync::atomic::{AtomicU32, Ordering};
+use core::{
+    mem::offset_of,
+    sync::atomic::{AtomicU32, Ordering},
+};
 use cortex_m_rt::entry;
 
 use defmt_rtt as _;
 use panic_halt as _;
 use probe_plotter::{
-    make_metric, make_metric_from_address, make_metric_from_base_with_offset, make_ptr,
-    make_setting,
+    Metricable, Plottable,
+    fixed::{Fixed, Q15},
+    make_action, make_metric, make_metric_array, make_metric_array_family,
+    make_metric_from_address, make_metric_from_base_with_offset, make_metric_from_symbol,
+    make_metric_group, make_ptr, make_registry, make_setting, make_setting_from_address,
 };
 
-#[unsafe(no_mangle)]
+/// Shown by variant name on the host side
+#[derive(Clone, Copy, Metricable)]
+#[repr(u8)]
+enum Mode {
+    Sawtooth,
+    Sine,
+    Off,
+}
+
+/// Every field is shown as a metric of its own, `STATS.counter`, `STATS.ratio` and `STATS.mode`
+#[derive(Clone, Copy, Plottable)]
+struct Stats {
+    counter: u32,
+    ratio: f32,
+    mode: Mode,
+}
+
+/// Committed as a whole, `CURRENTS.d` and `CURRENTS.q` are always from the same iteration
+#[derive(Clone, Copy, Plottable)]
+struct Currents {
+    d: f32,
+    q: f32,
+}
+
+/// Reached from `CTX` by following the `motor` pointer, see `motor.current` below
+struct Motor {
+    enabled: bool,
+    current: i32,
+}
+
+struct Ctx {
+    ticks: u32,
+    motor: *mut Motor,
+}
+
 static MY_ATOMIC: AtomicU32 = AtomicU32::new(42);
 
+// Address read by the host from the pointer to the static stored by the macro
+make_metric_from_symbol!(MY_ATOMIC: u32, "MY_ATOMIC % 1000");
+
 // Hardcoded address
 make_metric_from_address!(DWT_CYCCNT: i8 @ 0xE0001004, "DWT_CYCCNT");
 
+// Peripheral register written directly by the host, the compare value of TIM1 channel 1 on the
+// STM32G474. Only takes effect once the timer is clocked.
+make_setting_from_address!(TIM1_CCR1: u16 @ 0x40012C34, 0..=1000, 1);
+
+// Bitfields of registers, the number of comparators of the DWT and the counter enable bit of TIM1
+make_metric_from_address!(DWT_CTRL.NUMCOMP: u32 @ 0xE0001000 [31:28]);
+make_setting_from_address!(TIM1_CR1.CEN: u32 @ 0x40012C00 [0]);
+
+// Metrics added and removed at runtime
+make_registry!(REGISTRY: 8);
+
+// Timestamp of pushed samples and metric group commits, the cycle counter running at the 16 MHz of the HSI oscillator
+probe_plotter::timestamp!(16_000_000, cortex_m::peripheral::DWT::cycle_count());
+
 #[entry]
 fn main() -> ! {
     defmt::println!("Running...");
 
+    let mut cp = cortex_m::Peripherals::take().unwrap();
+    cp.DCB.enable_trace();
+    cp.DWT.enable_cycle_counter();
+
     let mut my_base_ptr = make_ptr!(BASE_THING).unwrap();
     let mut base_thing: [u8; 10] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9];
-    my_base_ptr.set(&base_thing as *const _ as u32); // Ensure this is something that will effectivly live as long as this or any depending values will be plotted
+    my_base_ptr.set(&base_thing as *const _ as usize); // Ensure this is something that will effectivly live as long as this or any depending values will be plotted
 
     make_metric_from_base_with_offset!(root.path.child: u8 @ BASE_THING + 3, "root.path.child");
 
+    // ctx->motor->current, the host reads the `motor` pointer to find `current`
+    let mut motor = Motor {
+        enabled: true,
+        current: 0,
+    };
+    let mut ctx = Ctx {
+        ticks: 0,
+        motor: &mut motor,
+    };
+    let mut ctx_ptr = make_ptr!(CTX).unwrap();
+    ctx_ptr.set(&ctx as *const _ as usize);
+    make_metric_from_base_with_offset!(
+        motor.current: i32 @ CTX + offset_of!(Ctx, motor) -> offset_of!(Motor, current),
+        "motor.current",
+        unit = "mA",
+        group = "motor"
+    );
+
     let mut sawtooth = make_metric!(SAWTOOTH: i32 = 42, "(SAWTOOTH / 10) % 100").unwrap();
     //defmt::println!("sawtooth initialized to: {}", sawtooth.get());
-    let mut sine = make_metric!(SINE: i32 = 42, "100 * sin(2 * pi * SINE / 4000)").unwrap();
+    let mut sine = make_metric!(
+        SINE: i32 = 42,
+        "100 * sin(2 * pi * SINE / 4000)",
+        color = "#ff8000"
+    )
+    .unwrap();
+
+    // 64 bit values are never seen half updated by the host, even on 32 bit targets
+    let mut iterations = make_metric!(ITERATIONS: u64 = 0, "ITERATIONS").unwrap();
+
+    // Shown as a button on the host side
+    let mut reset_iterations = make_action!(RESET_ITERATIONS).unwrap();
 
     let mut setting_roundtrip =
         make_metric!(SETTING_ROUNDTRIP: i8 = 0, "SETTING_ROUNDTRIP").unwrap();
 
-    // Allow values -1..=7, step by 2, so {-1, 1, 3, 5, 7}
-    let mut setting = make_setting!(SETTING: i8 = 5, -1..=7, 2).unwrap();
+    // Allow values -1..=7, step by 2, so {-1, 1, 3, 5, 7}. The target rejects -1, which is shown
+    // next to the setting on the host side
+    let mut setting = make_setting!(SETTING: i8 = 5, -1..=7, 2)
+        .unwrap()
+        .with_validation(|x| (x != -1).then_some(x));
+
+    // Counts how many times the host has written SETTING
+    let mut setting_changes = make_metric!(SETTING_CHANGES: u32 = 0).unwrap();
+
+    // Shown as a checkbox on the host side
+    let mut blink_enabled = make_setting!(BLINK_ENABLED: bool = true).unwrap();
+
+    // Plotted as a digital trace
+    let mut blink = make_metric!(BLINK: bool = false).unwrap();
+
+    // Fixed point values are shown as real values on the host side
+    let mut gain = make_setting!(
+        GAIN: Q15 = Q15::from_f32(0.5),
+        -1.0..=0.99,
+        0.01,
+        description = "Scales GAIN_ROUNDTRIP"
+    )
+    .unwrap();
+    let mut gain_roundtrip = make_metric!(GAIN_ROUNDTRIP: Q3_12 = Fixed(0)).unwrap();
+
+    // The whole array is shown as one waveform, updated every time it is published
+    let mut waveform = make_metric_array!(WAVEFORM: [i16; 64]).unwrap();
+    let mut waveform_buffer = [0; 64];
+
+    // Shown as a drop down menu on the host side
+    let mut mode_setting = make_setting!(MODE_SETTING: Mode = Mode::Sawtooth).unwrap();
+    let mut mode = make_metric!(MODE: Mode = Mode::Sawtooth).unwrap();
+
+    let mut stats = make_metric!(
+        STATS: Stats = Stats {
+            counter: 0,
+            ratio: 0.0,
+            mode: Mode::Sawtooth,
+        }
+    )
+    .unwrap();
+
+    // Shown as `CHANNEL.0.level` up to `CHANNEL.3.level` on the host side
+    let mut levels =
+        make_metric_array_family!(CHANNEL[4].level: u16 = 0, "CHANNEL__level / 10").unwrap();
+
+    // Every value is plotted at the time it was pushed, even though they change much faster than
+    // the host reads metrics
+    let mut ripple = make_metric!(RIPPLE: i16 = 0, unit = "mA").unwrap();
+
+    // Both fields are plotted at the time of the commit
+    let mut currents = make_metric_group!(CURRENTS: Currents, unit = "A", group = "motor").unwrap();
+
+    // Comes and goes at runtime, shown as `burst.N` on the host side while it exists
+    let mut burst = None;
 
     loop {
         for i in 0..i32::MAX {
-            sawtooth.set(i);
-            sine.set(i);
+            let current_mode = mode_setting.get();
+            mode.set(current_mode);
+            match current_mode {
+                Mode::Sawtooth => sawtooth.set(i),
+                Mode::Sine => sine.set(i),
+                Mode::Off => {}
+            }
             MY_ATOMIC.fetch_add(1, Ordering::SeqCst);
+            let n = iterations.get();
+            iterations.set(n + 1);
+            if reset_iterations.take() {
+                iterations.set(0);
+            }
 
             setting_roundtrip.set(setting.get());
+            if setting.changed() {
+                let n = setting_changes.get();
+                setting_changes.set(n + 1);
+            }
+            gain_roundtrip.set(Fixed(gain.get().to_bits() >> 3));
+            blink.set(blink_enabled.get() && (i / 16) % 2 == 0);
+
+            if i % 64 == 0 {
+                for (j, x) in waveform_buffer.iter_mut().enumerate() {
+                    *x = ((i / 64 + j as i32) % 64) as i16;
+                }
+                waveform.publish(&waveform_buffer);
+            }
+
+            stats.set(Stats {
+                counter: i as u32,
+                ratio: (i % 100) as f32 / 100.0,
+                mode: current_mode,
+            });
+
+            for (channel, level) in levels.iter_mut().enumerate() {
+                level.set(((i as usize * (channel + 1)) % 1000) as u16);
+            }
+
+            if i % 512 == 0 {
+                burst = match burst {
+                    Some(_) => None,
+                    None => REGISTRY.register("burst", (i / 1024) as u32 % 4, 0i32),
+                };
+            }
+            if let Some(burst) = &mut burst {
+                burst.set(i % 512);
+            }
+
+            // Written through the pointer like a driver holding `ctx` would
+            ctx.ticks = ctx.ticks.wrapping_add(1);
+            unsafe {
+                if (*ctx.motor).enabled {
+                    (&raw mut (*ctx.motor).current).write_volatile((ctx.ticks % 300) as i32 - 150);
+                }
+            }
+
+            // `q` is always `-d`, read separately they would be from different iterations
+            let d = (i % 100) as f32 / 100.0;
+            currents.set(Currents { d, q: -d });
+            currents.commit();
+
+            for j in 0..16 {
+                ripple.push(if j % 2 == 0 { 100 } else { -100 });
+                cortex_m::asm::delay(1_000);
+            }
 
             let idx = i as usize % base_thing.len();
             base_thing[idx] = base_thing[idx].wrapping_add(1);
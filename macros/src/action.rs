@@ -0,0 +1,54 @@
+use probe_plotter_common::symbol::Symbol;
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{
+    parse::{self, Parse, ParseStream},
+    parse_macro_input,
+};
+
+use crate::{export, parse_name};
+
+pub(crate) fn make_action(args: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as Args);
+
+    let (sym_name, record) = export(&Symbol::Action { name: args.name });
+    let static_name = args.static_name;
+
+    quote!(
+        {
+            #record
+
+            #[used]
+            #[unsafe(export_name = #sym_name)]
+            #[allow(non_upper_case_globals)]
+            static mut #static_name: (::probe_plotter::action::ActionStorage, bool) =
+                (::probe_plotter::action::ActionStorage::ZERO, false);
+
+            #[allow(unsafe_code)]
+            let used = unsafe { !::probe_plotter::sync::take(&raw mut #static_name.1) };
+            if used {
+                None
+            } else {
+                #[allow(unsafe_code)]
+                unsafe {
+                    Some(::probe_plotter::Action::new(&raw mut #static_name.0))
+                }
+            }
+        }
+    )
+    .into()
+}
+
+// RESET_INTEGRATOR
+struct Args {
+    name: String,
+    static_name: syn::Ident,
+}
+
+impl Parse for Args {
+    fn parse(input: ParseStream) -> parse::Result<Self> {
+        let (static_name, name, _name_span) = parse_name(&input)?;
+
+        Ok(Args { name, static_name })
+    }
+}
@@ -2,45 +2,141 @@
 
 extern crate proc_macro;
 
-use probe_plotter_common::strip_dots;
+use probe_plotter_common::{
+    PrimitiveType, parse_expr, record, strip_dots,
+    symbol::{Bits, Metadata, Symbol, parse_color},
+};
 use proc_macro::TokenStream;
 use proc_macro2::Span;
+use quote::{quote, quote_spanned};
 use syn::{
     Token,
     parse::{self, ParseStream},
     spanned::Spanned,
 };
 
+mod action;
 mod metric;
+mod metric_array;
+mod metric_array_family;
 mod metric_from_address;
 mod metric_from_base_with_offset;
+mod metric_from_symbol;
+mod metric_group;
+mod metricable;
+mod plottable;
 mod ptr;
+mod registry;
 mod setting;
+mod timestamp;
 
 /// Create a Metric instance that will be shown in the probe-plotter utility's graph
 ///
 /// ```
-/// make_metric!(NAME_AS_SHOWN_IN_GRAPH: DataType = defalt_value, "expression to convert from raw value (x) to the value to plot")
+/// make_metric!(NAME_AS_SHOWN_IN_GRAPH: DataType = defalt_value, "expression to convert from the raw value, named as the metric, to the value to plot")
 /// ```
 ///
 /// Note that similar to `cortex_m::singleton!`, this should only be called once per metric. The macro will only return Some() the first time, then None.
 ///
 /// ```
-/// let mut metric_foo = probe_plotter::make_metric!(FOO: i32 = 0, "x * 3.0").unwrap();
+/// let mut metric_foo = probe_plotter::make_metric!(FOO: i32 = 0, "FOO * 3.0").unwrap();
+///
+/// metric_foo.set(42); // The value 42 will be available for the host after this call. The value will be plotted as FOO * 3 = 42 * 3 = 126
+/// ```
 ///
-/// metric_foo.set(42); // The value 42 will be available for the host after this call. The value will be plotted as x * 3 = 42 * 3 = 126
+/// This and the other metric and setting macros optionally take `unit`, `description`, `color` and
+/// `group` at the end, in any order. Metrics in the same group are shown below the group in rerun.
+///
+/// ```
+/// let mut current = probe_plotter::make_metric!(CURRENT: i32 = 0, "CURRENT / 1000", unit = "A", color = "#ff0000", group = "motor").unwrap();
+/// let mut limit = probe_plotter::make_setting!(LIMIT: u16 = 100, 0..=1000, unit = "mA", description = "Current limit").unwrap();
 /// ```
 #[proc_macro]
 pub fn make_metric(args: TokenStream) -> TokenStream {
     metric::make_metric(args)
 }
 
+/// Create a MetricArray instance that will be shown as a waveform in the probe-plotter utility
+///
+/// ```
+/// make_metric_array!(NAME_AS_SHOWN_IN_GRAPH: [DataType; length], "expression to apply to every element before plotting")
+/// ```
+///
+/// All elements are initialized to zero. Note that similar to `cortex_m::singleton!`, this should only be
+/// called once per metric. The macro will only return Some() the first time, then None.
+///
+/// ```
+/// let mut adc_buffer = probe_plotter::make_metric_array!(ADC_BUFFER: [i16; 256], "ADC_BUFFER * 3.3 / 4096").unwrap();
+///
+/// adc_buffer.publish(&dma_buffer); // All 256 values will be available for the host after this call
+/// ```
+#[proc_macro]
+pub fn make_metric_array(args: TokenStream) -> TokenStream {
+    metric_array::make_metric_array(args)
+}
+
+/// Create a MetricGroup instance whose fields are always read together by the host
+///
+/// ```
+/// make_metric_group!(NAME_AS_SHOWN_IN_GRAPH: StructWithDerivePlottable)
+/// ```
+///
+/// Every field is shown as a metric of its own, `NAME.field`, plotted at the time of the last
+/// `commit()`. This requires `timestamp!`. All fields start out as zero. Note that similar to
+/// `cortex_m::singleton!`, this should only be called once per group. The macro will only return
+/// Some() the first time, then None.
+///
+/// ```
+/// let mut currents = probe_plotter::make_metric_group!(CURRENTS: Currents, unit = "A").unwrap();
+///
+/// currents.set(Currents { d, q });
+/// currents.commit(); // Both values will be available for the host after this call
+/// ```
+#[proc_macro]
+pub fn make_metric_group(args: TokenStream) -> TokenStream {
+    metric_group::make_metric_group(args)
+}
+
+/// Create an array of Metric instances for code which runs once per peripheral, motor etc.
+///
+/// ```
+/// make_metric_array_family!(NAME[count].rest.of.name: DataType = defalt_value, "expression")
+/// ```
+///
+/// Every instance gets the name with the index added as a part of its own, `MOTOR[4].current`
+/// will be shown as `MOTOR.0.current` up to `MOTOR.3.current` on the host side. In the expression the
+/// value is referred to by the name without index, `MOTOR__current`, which is replaced by the name
+/// of every instance. All instances start out with `defalt_value`.
+///
+/// Note that similar to `cortex_m::singleton!`, this should only be called once per family. The macro will only return Some() the first time, then None.
+///
+/// ```
+/// let mut currents = probe_plotter::make_metric_array_family!(MOTOR[4].current: i32 = 0, "MOTOR__current / 1000").unwrap();
+///
+/// for (metric, motor) in currents.iter_mut().zip(&motors) {
+///     metric.set(motor.current());
+/// }
+/// ```
+#[proc_macro]
+pub fn make_metric_array_family(args: TokenStream) -> TokenStream {
+    metric_array_family::make_metric_array_family(args)
+}
+
 /// Create a Setting instance that will be shown as a slider in the probe-plotter utility
 ///
 /// ```
 /// make_setting!(NAME_AS_SHOWN_NEXT_TO_SLIDER: DataType = defalt_value, min_value..=max_value, step_size)
 /// ```
 ///
+/// The step size defaults to 1, 0.01 for floats and the resolution for fixed point types. The range
+/// defaults to the full range of the type. Both can be left out for bool and enum settings, which
+/// are shown as a checkbox and a drop down menu.
+///
+/// ```
+/// make_setting!(FOO: i32 = 0, 0..=10); // Step size 1
+/// make_setting!(BAR: u8 = 0); // Range 0..=255, step size 1
+/// ```
+///
 /// Note that similar to `cortex_m::singleton!`, this should only be called once per setting. The macro will only return Some() the first time, then None.
 ///
 /// ```
@@ -53,6 +149,159 @@ pub fn make_setting(args: TokenStream) -> TokenStream {
     setting::make_setting(args)
 }
 
+/// Tell probe-plotter-tools about an existing value at the provided address, such as a peripheral
+/// register, which should be shown as a setting
+///
+/// ```
+/// make_setting_from_address!(NAME: DataType @ address, min_value..=max_value, step_size)
+/// ```
+///
+/// The range and step size default the same way as for [make_setting]. Unlike [make_setting]
+/// there is no initial value and no `Setting` object, the host reads and writes the value as is in
+/// memory and every write takes effect immediately, without the target applying it.
+///
+/// ```rust
+/// // PWM compare value of timer 1, channel 1
+/// probe_plotter::make_setting_from_address!(TIM1_CCR1: u16 @ 0x40012C34, 0..=1000, 1);
+/// ```
+///
+/// Bits of a register can be selected the same way as for [make_metric_from_address]. The range
+/// then defaults to every value the bits can hold. Writes only change those bits, the rest of the
+/// register is read and written back as is.
+///
+/// ```rust
+/// // Counter enable, bit 0 of TIM1_CR1
+/// probe_plotter::make_setting_from_address!(TIM1_CR1.CEN: u32 @ 0x40012C00 [0]);
+/// ```
+#[proc_macro]
+pub fn make_setting_from_address(args: TokenStream) -> TokenStream {
+    setting::make_setting_from_address(args)
+}
+
+/// Create an Action instance that will be shown as a button in the probe-plotter utility
+///
+/// ```
+/// make_action!(NAME_AS_SHOWN_ON_BUTTON)
+/// ```
+///
+/// Note that similar to `cortex_m::singleton!`, this should only be called once per action. The macro will only return Some() the first time, then None.
+///
+/// ```
+/// let mut reset_integrator = probe_plotter::make_action!(RESET_INTEGRATOR).unwrap();
+///
+/// if reset_integrator.take() {
+///     integrator = 0.0;
+/// }
+/// ```
+#[proc_macro]
+pub fn make_action(args: TokenStream) -> TokenStream {
+    action::make_action(args)
+}
+
+/// Allow an enum to be used as the type of a metric or setting
+///
+/// The host will show the name of the current variant of metrics and a drop down menu for settings.
+/// The enum needs to be `Copy`, have no fields and have an integer `#[repr]` of at most 32 bits.
+/// The enum is referred to by name on the host side, so the name needs to be unique within the
+/// firmware.
+///
+/// ```
+/// #[derive(Clone, Copy, probe_plotter::Metricable)]
+/// #[repr(u8)]
+/// enum State {
+///     Idle,
+///     Running,
+///     Fault = 10,
+/// }
+///
+/// let mut state = probe_plotter::make_metric!(STATE: State = State::Idle).unwrap();
+/// let mut mode = probe_plotter::make_setting!(MODE: State = State::Idle).unwrap();
+///
+/// state.set(mode.get());
+/// ```
+#[proc_macro_derive(Metricable)]
+pub fn derive_metricable(input: TokenStream) -> TokenStream {
+    metricable::derive_metricable(input)
+}
+
+/// Allow a struct to be used as the type of a metric, plotting every field
+///
+/// Every field with a primitive type, a fixed point type or a type which itself derives `Metricable`
+/// or `Plottable` is shown on the host as a metric called `METRIC_NAME.field_name`. Nested structs
+/// result in `METRIC_NAME.field_name.inner_field_name`. Fields of any other type are skipped. The
+/// struct needs to be `Copy` and is referred to by name on the host side, so the name needs to be
+/// unique within the firmware.
+///
+/// ```
+/// #[derive(Clone, Copy, probe_plotter::Plottable)]
+/// struct MotorState {
+///     current: i32,
+///     speed: f32,
+///     enabled: bool,
+/// }
+///
+/// let mut motor = probe_plotter::make_metric!(MOTOR: MotorState = MotorState {
+///     current: 0,
+///     speed: 0.0,
+///     enabled: false,
+/// }).unwrap();
+///
+/// // The host will show `MOTOR.current`, `MOTOR.speed` and `MOTOR.enabled`
+/// motor.set(MotorState { current: 120, speed: 3.5, enabled: true });
+/// ```
+#[proc_macro_derive(Plottable)]
+pub fn derive_plottable(input: TokenStream) -> TokenStream {
+    plottable::derive_plottable(input)
+}
+
+/// Create a registry for metrics which are added and removed at runtime
+///
+/// ```
+/// make_registry!(NAME_OF_STATIC: capacity);
+/// ```
+///
+/// This creates a static which can hold up to `capacity` metrics at the same time. The host will
+/// periodically check the registry and add or remove plots as metrics are added and removed.
+///
+/// ```
+/// probe_plotter::make_registry!(REGISTRY: 16);
+///
+/// let mut packets = REGISTRY.register("usb.ep", 3, 0u32).unwrap(); // Shown as `usb.ep.3`
+/// packets.set(42);
+/// ```
+#[proc_macro]
+pub fn make_registry(args: TokenStream) -> TokenStream {
+    registry::make_registry(args)
+}
+
+/// Define the timestamp of the samples sent with `Metric::push` and of the commits of
+/// `MetricGroup`s, once per application
+///
+/// ```
+/// timestamp!(ticks per second, expression returning the current time in ticks as u32)
+/// ```
+///
+/// The host keeps track of the timestamp wrapping around, as long as there is at least one
+/// sample every wrap around.
+///
+/// ```
+/// // The cycle counter of a Cortex-M running at 170 MHz, which has to be enabled first
+/// probe_plotter::timestamp!(170_000_000, cortex_m::peripheral::DWT::cycle_count());
+/// ```
+#[proc_macro]
+pub fn timestamp(args: TokenStream) -> TokenStream {
+    timestamp::timestamp(args)
+}
+
+/// Internal use only by `probe_plotter`, the version of the metadata format of the records written
+/// by these macros as a `u32` literal
+#[doc(hidden)]
+#[proc_macro]
+pub fn metadata_version(_args: TokenStream) -> TokenStream {
+    let version = u32::from(record::VERSION);
+    quote!(#version).into()
+}
+
 /// See [make_metric_from_base_with_offset] for more info
 #[proc_macro]
 pub fn make_ptr(args: TokenStream) -> TokenStream {
@@ -72,20 +321,66 @@ pub fn make_ptr(args: TokenStream) -> TokenStream {
 /// ```rust
 /// probe_plotter::make_metric_from_address(root.path.child: u8 @ 0x1234, "3 * root.path.child");
 /// ```
+///
+/// Add `[high:low]`, or `[bit]` for a single bit, after the address to only show those bits of the
+/// register. The type is then the unsigned integer type with the size of the whole register.
+///
+/// ```rust
+/// // Number of comparators, bits 31 to 28 of DWT_CTRL
+/// probe_plotter::make_metric_from_address!(DWT_CTRL.NUMCOMP: u32 @ 0xE0001000 [31:28]);
+/// ```
 #[proc_macro]
 pub fn make_metric_from_address(args: TokenStream) -> TokenStream {
     metric_from_address::make_metric_from_address(args)
 }
 
+/// Tell probe-plotter-tools about an existing static which should be shown as a metric
+///
+/// ```
+/// make_metric_from_symbol!(path::to::STATIC: DataType, "expression to convert from raw value to the value to plot")
+/// ```
+///
+/// The macro stores a pointer to the static which the host reads from the ELF file, so any static
+/// in scope can be used whatever its symbol is. `DataType` has to have the same size as the
+/// static, for example `u32` for an `AtomicU32`. The metric is shown with the name of the static.
+///
+/// As with [make_metric_from_address], the value is read as is from memory with no need to call
+/// `Metric::set`.
+///
+/// ```rust
+/// static MY_ATOMIC: AtomicU32 = AtomicU32::new(42);
+///
+/// probe_plotter::make_metric_from_symbol!(MY_ATOMIC: u32, "MY_ATOMIC / 1000");
+/// ```
+#[proc_macro]
+pub fn make_metric_from_symbol(args: TokenStream) -> TokenStream {
+    metric_from_symbol::make_metric_from_symbol(args)
+}
+
 /// Tell probe-plotter-tools about an existing value at a relative offset with an other metrics value as base
 ///
 /// ```rust
-/// let some_address: *const u8 = *const 0x1234;
-/// let mut my_ptr_metric = probe_plotter::make_ptr(MY_PTR_METRIC).unwrap();
-/// my_ptr_metric.set(some_address);
+/// let some_address: *const u8 = 0x1234 as *const u8;
+/// let mut my_ptr_metric = probe_plotter::make_ptr!(MY_PTR_METRIC).unwrap();
+/// my_ptr_metric.set(some_address as usize);
 /// probe_plotter::make_metric_from_address_with_offset(root.path.child: u8 @ MY_PTR_METRIC + 42, "3 * root.path.child");
 /// // The address of the metric `root.path.child` will be 0x1234 + 42
 /// ```
+///
+/// Offsets may be any constant expression up to `u32::MAX` such as
+/// `core::mem::offset_of!(Type, field)`. Use `->` to follow a pointer, like in C: the pointer at
+/// the address so far is read and the next offset is added to it. The host only follows pointers
+/// into RAM, the metric is not read otherwise.
+///
+/// ```rust
+/// use core::mem::offset_of;
+///
+/// // ctx->motor->state.current
+/// probe_plotter::make_metric_from_base_with_offset!(
+///     motor.current: i32 @ CTX + offset_of!(Ctx, motor) -> offset_of!(Motor, state.current),
+///     "motor.current"
+/// );
+/// ```
 #[proc_macro]
 pub fn make_metric_from_base_with_offset(args: TokenStream) -> TokenStream {
     metric_from_base_with_offset::make_metric_from_base_with_offset(args)
@@ -124,6 +419,38 @@ fn expr_to_float_lit(e: syn::Expr) -> Result<syn::LitFloat, syn::Error> {
     })
 }
 
+/// The Rust type to use in the generated code for the type written in the macro invocation
+///
+/// Fixed point types such as `Q15` are mapped to `probe_plotter::fixed::Fixed`
+pub(crate) fn rust_type(ty: &syn::Ident) -> proc_macro2::TokenStream {
+    let primitive_type = PrimitiveType::try_from(ty.to_string().as_str());
+    match primitive_type.map(|t| (t, t.fixed_point_storage())) {
+        Ok((PrimitiveType::Q { frac, .. }, Some(storage))) => {
+            let storage = syn::Ident::new(&storage.to_string(), ty.span());
+            let frac = proc_macro2::Literal::u32_unsuffixed(frac.into());
+            quote_spanned!(ty.span()=> ::probe_plotter::fixed::Fixed<#storage, #frac>)
+        }
+        _ => quote!(#ty),
+    }
+}
+
+/// (export_name, record) for the static described by `symbol`
+///
+/// The record is a static of its own in the `.probe_plotter` section, see
+/// `probe_plotter_common::record`
+pub(crate) fn export(symbol: &Symbol) -> (String, proc_macro2::TokenStream) {
+    let bytes = record::encode(symbol);
+    let len = bytes.len();
+    let record = quote! {
+        const _: () = {
+            #[used]
+            #[unsafe(link_section = ".probe_plotter")]
+            static RECORD: [u8; #len] = [#(#bytes),*];
+        };
+    };
+    (symbol.export_name(), record)
+}
+
 /// (static_name, name, name_span)
 pub(crate) fn parse_name(input: &ParseStream) -> parse::Result<(syn::Ident, String, Span)> {
     let name =
@@ -144,12 +471,107 @@ pub(crate) fn parse_expr_str(
     name: &str,
     name_span: Span,
 ) -> parse::Result<syn::LitStr> {
-    let comma: parse::Result<Token![,]> = input.parse();
-    let expression_string: parse::Result<syn::LitStr> = input.parse();
+    // Anything else after the comma is metadata, see `parse_metadata`
+    if !(input.peek(Token![,]) && input.peek2(syn::LitStr)) {
+        return Ok(syn::LitStr::new(&strip_dots(name), name_span));
+    }
+    let _comma: Token![,] = input.parse()?;
+    let expr: syn::LitStr = input.parse()?;
+    parse_expr(&expr.value(), &strip_dots(name)).map_err(|e| syn::Error::new(expr.span(), e))?;
+    Ok(syn::LitStr::new(&expr.value(), name_span))
+}
+
+/// Whether the rest of the input is empty or metadata, see [parse_metadata]
+pub(crate) fn at_metadata(input: &ParseStream) -> bool {
+    input.is_empty() || (input.peek(Token![,]) && input.peek2(syn::Ident))
+}
 
-    match (comma, expression_string) {
-        (Ok(_), Ok(expr)) => Ok(syn::LitStr::new(&expr.value(), name_span)),
-        (Ok(_), Err(e)) => Err(e),
-        (Err(_), _) => Ok(syn::LitStr::new(&strip_dots(name), name_span)),
+/// Optional `, unit = "A", description = "...", color = "#ff0000", group = "motor"` in any order
+pub(crate) fn parse_metadata(input: &ParseStream) -> parse::Result<Metadata> {
+    let mut meta = Metadata::default();
+    while input.peek(Token![,]) {
+        let _comma: Token![,] = input.parse()?;
+        if input.is_empty() {
+            break;
+        }
+
+        let key: syn::Ident = input.parse()?;
+        let _eq: Token![=] = input.parse()?;
+        let value: syn::LitStr = input.parse()?;
+        let field = match key.to_string().as_str() {
+            "unit" => &mut meta.unit,
+            "description" => &mut meta.description,
+            "color" => {
+                if parse_color(&value.value()).is_none() {
+                    return Err(syn::Error::new(
+                        value.span(),
+                        "Expected a color of the form \"#rrggbb\"",
+                    ));
+                }
+                &mut meta.color
+            }
+            "group" => &mut meta.group,
+            _ => {
+                return Err(syn::Error::new(
+                    key.span(),
+                    "Expected one of unit, description, color or group",
+                ));
+            }
+        };
+        if field.replace(value.value()).is_some() {
+            return Err(syn::Error::new(key.span(), format!("{key} is set twice")));
+        }
+    }
+    Ok(meta)
+}
+
+/// Optional `[high:low]` or `[bit]` after a hardcoded address, selecting a bitfield of the `ty`
+/// sized word at that address
+pub(crate) fn parse_bits(
+    input: &ParseStream,
+    ty: &str,
+    ty_span: Span,
+) -> parse::Result<Option<Bits>> {
+    if !input.peek(syn::token::Bracket) {
+        return Ok(None);
+    }
+    let content;
+    let brackets = syn::bracketed!(content in input);
+    let high: syn::LitInt = content.parse()?;
+    let low = if content.peek(Token![:]) {
+        let _colon: Token![:] = content.parse()?;
+        content.parse::<syn::LitInt>()?
+    } else {
+        high.clone()
+    };
+    let bits = Bits {
+        high: high.base10_parse()?,
+        low: low.base10_parse()?,
+    };
+
+    let width = match PrimitiveType::try_from(ty) {
+        Ok(PrimitiveType::u8) => 8,
+        Ok(PrimitiveType::u16) => 16,
+        Ok(PrimitiveType::u32) => 32,
+        Ok(PrimitiveType::u64) => 64,
+        _ => {
+            return Err(syn::Error::new(
+                ty_span,
+                "Bitfields need an unsigned integer type with the size of the whole register",
+            ));
+        }
+    };
+    if bits.high < bits.low {
+        return Err(syn::Error::new(
+            brackets.span.join(),
+            format!("Expected [high:low], {} is below {}", bits.high, bits.low),
+        ));
+    }
+    if bits.high >= width {
+        return Err(syn::Error::new(
+            high.span(),
+            format!("Bit {} is outside of {ty}", bits.high),
+        ));
     }
+    Ok(Some(bits))
 }
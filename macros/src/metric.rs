@@ -1,4 +1,4 @@
-use probe_plotter_common::symbol::Symbol;
+use probe_plotter_common::symbol::{Metadata, Symbol};
 use proc_macro::TokenStream;
 use quote::quote;
 use syn::{
@@ -7,7 +7,7 @@ use syn::{
     parse_macro_input,
 };
 
-use crate::{parse_expr_str, parse_name};
+use crate::{export, parse_expr_str, parse_metadata, parse_name, rust_type};
 
 pub fn make_metric(args: TokenStream) -> TokenStream {
     let args = parse_macro_input!(args as Args);
@@ -15,50 +15,54 @@ pub fn make_metric(args: TokenStream) -> TokenStream {
 }
 
 pub(crate) fn metric_helper(args: Args) -> TokenStream {
-    let sym_name = serde_json::to_string(&Symbol::Metric {
-        ty: args.ty.to_string().as_str().try_into().unwrap(),
+    let (sym_name, record) = export(&Symbol::Metric {
+        ty: args.ty.to_string().as_str().into(),
         name: args.name.to_string(),
         expr: args.expression_string.map(|x| x.value()),
         address: probe_plotter_common::symbol::Address::Symbols,
-    })
-    .unwrap();
+        meta: args.meta,
+    });
 
-    let ty = args.ty;
+    let ty = rust_type(&args.ty);
     let initial_value = args.initial_val;
     let static_name = args.static_name;
 
     quote!(
-        cortex_m::interrupt::free(|_| {
+        {
+            #record
+
             #[used]
             #[unsafe(export_name = #sym_name)]
             #[allow(non_upper_case_globals)]
-            static mut #static_name: (#ty, bool) =
-                (0 as _, false);
+            static mut #static_name: (<#ty as ::probe_plotter::metric::Metricable>::Storage, bool) =
+                (<#ty as ::probe_plotter::metric::Metricable>::ZERO, false);
 
             #[allow(unsafe_code)]
-            let used = unsafe { #static_name.1 };
+            let used = unsafe { !::probe_plotter::sync::take(&raw mut #static_name.1) };
             if used {
                 None
             } else {
                 #[allow(unsafe_code)]
                 unsafe {
-                    #static_name.1 = true;
-                    #static_name.0 = #initial_value;
-                    Some(::probe_plotter::Metric::new(&mut #static_name.0))
+                    let initial_value: #ty = #initial_value;
+                    <#ty as ::probe_plotter::metric::Metricable>::store(&mut #static_name.0, initial_value);
+                    Some(::probe_plotter::Metric::<#ty>::new(&mut #static_name.0))
                 }
             }
-        })
+        }
     )
     .into()
 }
 
 //FOO: i32 = 0, "FOO * 3.0"
 //FOO: i32 = 0 // defaults to "FOO"
+//FOO: i32 = 0, "FOO * 3.0", unit = "mA", group = "motor" // see `parse_metadata`
 pub(crate) struct Args {
     pub(crate) name: String,
     pub(crate) ty: syn::Ident,
     pub(crate) initial_val: syn::Expr,
     pub(crate) expression_string: Option<syn::LitStr>,
+    pub(crate) meta: Metadata,
     pub(crate) static_name: syn::Ident,
 }
 
@@ -71,12 +75,14 @@ impl Parse for Args {
         let initial_val = input.parse()?;
 
         let expression_string = parse_expr_str(&input, &name, name_span)?;
+        let meta = parse_metadata(&input)?;
 
         Ok(Self {
             name,
             ty,
             initial_val,
             expression_string: Some(expression_string),
+            meta,
             static_name,
         })
     }
@@ -0,0 +1,88 @@
+use probe_plotter_common::symbol::{Metadata, Symbol};
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{
+    Token,
+    parse::{self, Parse, ParseStream},
+    parse_macro_input,
+};
+
+use crate::{export, parse_expr_str, parse_metadata, parse_name, rust_type};
+
+pub fn make_metric_array(args: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as Args);
+
+    let (sym_name, record) = export(&Symbol::MetricArray {
+        ty: args.ty.to_string().as_str().into(),
+        name: args.name.to_string(),
+        expr: Some(args.expression_string.value()),
+        len: args.len,
+        meta: args.meta,
+    });
+
+    let ty = rust_type(&args.ty);
+    let len = proc_macro2::Literal::u64_unsuffixed(args.len);
+    let static_name = args.static_name;
+
+    quote!(
+        {
+            #record
+
+            #[used]
+            #[unsafe(export_name = #sym_name)]
+            #[allow(non_upper_case_globals)]
+            static mut #static_name: (::probe_plotter::metric_array::ArrayStorage<#ty, #len>, bool) =
+                (::probe_plotter::metric_array::ArrayStorage::ZERO, false);
+
+            #[allow(unsafe_code)]
+            let used = unsafe { !::probe_plotter::sync::take(&raw mut #static_name.1) };
+            if used {
+                None
+            } else {
+                #[allow(unsafe_code)]
+                unsafe {
+                    Some(::probe_plotter::MetricArray::<#ty, #len>::new(&mut #static_name.0))
+                }
+            }
+        }
+    )
+    .into()
+}
+
+// FOO: [i16; 256], "FOO * 3.0"
+// FOO: [i16; 256] // defaults to "FOO"
+// FOO: [i16; 256], "FOO * 3.0", unit = "V", group = "adc" // see `parse_metadata`
+pub(crate) struct Args {
+    pub(crate) name: String,
+    pub(crate) ty: syn::Ident,
+    pub(crate) len: u64,
+    pub(crate) expression_string: syn::LitStr,
+    pub(crate) meta: Metadata,
+    pub(crate) static_name: syn::Ident,
+}
+
+impl Parse for Args {
+    fn parse(input: ParseStream) -> parse::Result<Self> {
+        let (static_name, name, name_span) = parse_name(&input)?;
+        let _colon: Token![:] = input.parse()?;
+
+        let content;
+        syn::bracketed!(content in input);
+        let ty = content.parse()?;
+        let _semi: Token![;] = content.parse()?;
+        let len: syn::LitInt = content.parse()?;
+        let len = len.base10_parse()?;
+
+        let expression_string = parse_expr_str(&input, &name, name_span)?;
+        let meta = parse_metadata(&input)?;
+
+        Ok(Self {
+            name,
+            ty,
+            len,
+            expression_string,
+            meta,
+            static_name,
+        })
+    }
+}
@@ -0,0 +1,222 @@
+use probe_plotter_common::{
+    strip_dots,
+    symbol::{Metadata, Symbol},
+};
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::{
+    Token,
+    parse::{self, Parse, ParseStream},
+    parse_macro_input,
+};
+
+use crate::{export, parse_expr_str, parse_metadata, rust_type};
+
+pub fn make_metric_array_family(args: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as Args);
+
+    let ty = rust_type(&args.ty);
+    let initial_value = &args.initial_val;
+
+    // The expression refers to the value by the name without index, replace that by the name of
+    // every instance
+    let family_variable = strip_dots(&args.name(None));
+
+    let mut static_names = Vec::new();
+    let mut sym_names = Vec::new();
+    let mut records = Vec::new();
+    for i in 0..args.len {
+        let name = args.name(Some(i));
+        let expr = replace_variable(
+            &args.expression_string.value(),
+            &family_variable,
+            &strip_dots(&name),
+        );
+
+        static_names.push(syn::Ident::new(&strip_dots(&name), args.name_span));
+        let (sym_name, record) = export(&Symbol::Metric {
+            ty: args.ty.to_string().as_str().into(),
+            name,
+            expr: Some(expr),
+            address: probe_plotter_common::symbol::Address::Symbols,
+            meta: args.meta.clone(),
+        });
+        sym_names.push(sym_name);
+        records.push(record);
+    }
+
+    quote!(
+        {
+            static mut USED: bool = false;
+
+            #(#records)*
+
+            #(
+                #[used]
+                #[unsafe(export_name = #sym_names)]
+                #[allow(non_upper_case_globals)]
+                static mut #static_names: <#ty as ::probe_plotter::metric::Metricable>::Storage =
+                    <#ty as ::probe_plotter::metric::Metricable>::ZERO;
+            )*
+
+            #[allow(unsafe_code)]
+            let used = unsafe { !::probe_plotter::sync::take(&raw mut USED) };
+            if used {
+                None
+            } else {
+                #[allow(unsafe_code)]
+                unsafe {
+                    let initial_value: #ty = #initial_value;
+                    #(<#ty as ::probe_plotter::metric::Metricable>::store(&raw mut #static_names, initial_value);)*
+                    Some([#(::probe_plotter::Metric::<#ty>::new(&raw mut #static_names)),*])
+                }
+            }
+        }
+    )
+    .into()
+}
+
+/// Replace the variable `from` by `to` in `expr`, leaving longer names containing `from` untouched
+fn replace_variable(expr: &str, from: &str, to: &str) -> String {
+    let is_ident_char = |c: char| c.is_alphanumeric() || c == '_';
+
+    let mut out = String::new();
+    let mut rest = expr;
+    while let Some(pos) = rest.find(from) {
+        out.push_str(&rest[..pos]);
+        rest = &rest[pos + from.len()..];
+
+        let standalone = !out.chars().next_back().is_some_and(is_ident_char)
+            && !rest.chars().next().is_some_and(is_ident_char);
+        out.push_str(if standalone { to } else { from });
+    }
+    out.push_str(rest);
+    out
+}
+
+//MOTOR[4].current: i32 = 0, "MOTOR__current * 3.0"
+//MOTOR[4].current: i32 = 0 // defaults to "MOTOR__current"
+struct Args {
+    /// Every part of the name, such as `MOTOR` and `current`
+    segments: Vec<syn::Ident>,
+
+    /// Which part of the name is followed by `[len]`
+    index_position: usize,
+    len: usize,
+    name_span: Span,
+    ty: syn::Ident,
+    initial_val: syn::Expr,
+    expression_string: syn::LitStr,
+    meta: Metadata,
+}
+
+impl Args {
+    /// Name of instance `index`, or the name without index if `None`
+    fn name(&self, index: Option<usize>) -> String {
+        let mut parts = Vec::new();
+        for (i, segment) in self.segments.iter().enumerate() {
+            parts.push(segment.to_string());
+            if let Some(index) = index
+                && i == self.index_position
+            {
+                parts.push(index.to_string());
+            }
+        }
+        parts.join(".")
+    }
+}
+
+impl Parse for Args {
+    fn parse(input: ParseStream) -> parse::Result<Self> {
+        let name_span = input.span();
+        let mut segments = Vec::new();
+        let mut index = None;
+        loop {
+            let segment: syn::Ident = input.parse()?;
+            if input.peek(syn::token::Bracket) {
+                let content;
+                syn::bracketed!(content in input);
+                let len: syn::LitInt = content.parse()?;
+                if index.is_some() {
+                    return Err(syn::Error::new(
+                        len.span(),
+                        "Only one part of the name can have a number of instances",
+                    ));
+                }
+                index = Some((segments.len(), len.base10_parse()?));
+            }
+            segments.push(segment);
+
+            if !input.peek(Token![.]) {
+                break;
+            }
+            let _dot: Token![.] = input.parse()?;
+        }
+
+        let Some((index_position, len)) = index else {
+            return Err(syn::Error::new(
+                name_span,
+                "Expected the number of instances, for example MOTOR[4].current",
+            ));
+        };
+
+        let _colon: Token![:] = input.parse()?;
+        let ty = input.parse()?;
+        let _eq: Token![=] = input.parse()?;
+        let initial_val = input.parse()?;
+
+        let mut args = Self {
+            segments,
+            index_position,
+            len,
+            name_span,
+            ty,
+            initial_val,
+            expression_string: syn::LitStr::new("", name_span),
+            meta: Metadata::default(),
+        };
+        args.expression_string = parse_expr_str(&input, &args.name(None), name_span)?;
+        args.meta = parse_metadata(&input)?;
+
+        Ok(args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::replace_variable;
+
+    #[test]
+    fn standalone() {
+        assert_eq!(
+            replace_variable(
+                "MOTOR__current / 1000",
+                "MOTOR__current",
+                "MOTOR__2__current"
+            ),
+            "MOTOR__2__current / 1000"
+        );
+        assert_eq!(replace_variable("x", "x", "y"), "y");
+    }
+
+    #[test]
+    fn longer_names() {
+        // Prefix and suffix of a longer name
+        assert_eq!(replace_variable("x_offset + 1", "x", "y"), "x_offset + 1");
+        assert_eq!(replace_variable("max + 1", "x", "y"), "max + 1");
+        assert_eq!(
+            replace_variable("MOTOR__current2", "MOTOR__current", "M"),
+            "MOTOR__current2"
+        );
+        assert_eq!(replace_variable("xx", "x", "y"), "xx");
+    }
+
+    #[test]
+    fn several_matches() {
+        assert_eq!(
+            replace_variable("x * x + max(x, x_min)", "x", "y"),
+            "y * y + max(y, x_min)"
+        );
+    }
+}
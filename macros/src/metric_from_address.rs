@@ -1,6 +1,6 @@
 use probe_plotter_common::{
-    PrimitiveType,
-    symbol::{Address, Symbol},
+    PrimitiveType, ValueType,
+    symbol::{Address, Bits, Metadata, Symbol},
 };
 use proc_macro::TokenStream;
 use quote::quote;
@@ -10,22 +10,25 @@ use syn::{
     parse_macro_input,
 };
 
-use crate::{parse_expr_str, parse_name};
+use crate::{export, parse_bits, parse_expr_str, parse_metadata, parse_name};
 
 pub fn make_metric_from_address(args: TokenStream) -> TokenStream {
     let args = parse_macro_input!(args as Args);
 
-    let sym_name = serde_json::to_string(&Symbol::Metric {
-        ty: args.ty,
+    let (sym_name, record) = export(&Symbol::Metric {
+        ty: ValueType::Primitive(args.ty),
         name: args.name.clone(),
         expr: Some(args.expression_string.value()),
         address: Address::Hardcoded {
             address: args.address,
+            bits: args.bits,
         },
-    })
-    .unwrap();
+        meta: args.meta,
+    });
     let static_name = args.static_name;
     quote! {
+        #record
+
         #[used]
         #[unsafe(export_name = #sym_name)]
         #[allow(non_upper_case_globals)]
@@ -35,11 +38,14 @@ pub fn make_metric_from_address(args: TokenStream) -> TokenStream {
 }
 
 // root.child.leaf: i8 @ 0x1234, "root.child.leaf"
+// USART1_ISR.TXE: u32 @ 0x4001381C [7], "USART1_ISR.TXE"
 pub(crate) struct Args {
     pub(crate) name: String,
     pub(crate) ty: PrimitiveType,
     pub(crate) address: u64,
+    pub(crate) bits: Option<Bits>,
     pub(crate) expression_string: syn::LitStr,
+    pub(crate) meta: Metadata,
     pub(crate) static_name: syn::Ident,
 }
 
@@ -48,18 +54,23 @@ impl Parse for Args {
         let (static_name, name, name_span) = parse_name(&input)?;
 
         let _colon: Token![:] = input.parse()?;
-        let ty = input.parse()?;
+        let ty_span = input.span();
+        let ty: PrimitiveType = input.parse()?;
         let _at: Token![@] = input.parse()?;
         let address: syn::LitInt = input.parse()?;
         let address = address.base10_parse()?;
+        let bits = parse_bits(&input, &ty.to_string(), ty_span)?;
 
         let expression_string = parse_expr_str(&input, &name, name_span)?;
+        let meta = parse_metadata(&input)?;
 
         Ok(Args {
             name,
             ty,
             address,
+            bits,
             expression_string,
+            meta,
             static_name,
         })
     }
@@ -1,47 +1,63 @@
 use probe_plotter_common::{
-    PrimitiveType,
-    symbol::{Address, Symbol},
+    PrimitiveType, ValueType,
+    symbol::{Address, Metadata, Symbol},
 };
 use proc_macro::TokenStream;
 use quote::quote;
 use syn::{
-    LitInt, Token,
+    Token,
     parse::{self, Parse, ParseStream},
     parse_macro_input,
 };
 
-use crate::{parse_expr_str, parse_name};
+use crate::{export, parse_expr_str, parse_metadata, parse_name};
 
 pub fn make_metric_from_base_with_offset(args: TokenStream) -> TokenStream {
     let args = parse_macro_input!(args as Args);
 
-    let sym_name = serde_json::to_string(&Symbol::Metric {
-        ty: args.ty,
+    let (sym_name, record) = export(&Symbol::Metric {
+        ty: ValueType::Primitive(args.ty),
         name: args.name.clone(),
         expr: Some(args.expression_string.value()),
-        address: Address::RelativeBaseMetricWithOffset {
+        address: Address::PointerChain {
             base_metric: args.base_symbol.to_string(),
-            offset: args.offset,
+            len: args.offsets.len() as u64,
         },
-    })
-    .unwrap();
+        meta: args.meta,
+    });
     let static_name = args.static_name;
+    let offsets = args.offsets;
+    let len = offsets.len();
+
+    // The offsets may use `offset_of!`, so let the compiler evaluate them and have the host read
+    // them from the contents of the static, same as for `#[derive(Metricable)]`. The static is
+    // evaluated at compile time, so an offset which does not fit fails to compile.
     quote! {
+        #record
+
         #[used]
         #[unsafe(export_name = #sym_name)]
         #[allow(non_upper_case_globals)]
-        static #static_name: u8 = 0;
+        static #static_name: [u32; #len] = [#({
+            let offset = (#offsets) as usize;
+            assert!(offset <= u32::MAX as usize, "The offset does not fit in a u32");
+            offset as u32
+        }),*];
     }
     .into()
 }
 
 // root.child.leaf: i8 @ BASE_SYMBOL + 3, "root.child.leaf"
+// root.child.leaf: i8 @ BASE_SYMBOL + offset_of!(Ctx, motor) -> offset_of!(Motor, current)
 pub(crate) struct Args {
     pub(crate) name: String,
     pub(crate) ty: PrimitiveType,
     pub(crate) base_symbol: syn::Ident,
-    pub(crate) offset: u64,
+
+    /// Offset to add to the base, followed by the offset to add after each dereference
+    pub(crate) offsets: Vec<syn::Expr>,
     pub(crate) expression_string: syn::LitStr,
+    pub(crate) meta: Metadata,
     pub(crate) static_name: syn::Ident,
 }
 
@@ -52,20 +68,58 @@ impl Parse for Args {
         let _colon: Token![:] = input.parse()?;
         let ty = input.parse()?;
         let _at: Token![@] = input.parse()?;
-        let base_symbol = input.parse()?;
-        let _plus: Token![+] = input.parse()?;
-        let offset: LitInt = input.parse()?;
-        let offset = offset.base10_parse()?;
+        let base_symbol: syn::Ident = input.parse()?;
+
+        let first_offset = if input.peek(Token![+]) {
+            let _plus: Token![+] = input.parse()?;
+            parse_offset(input)?
+        } else {
+            syn::parse_quote!(0)
+        };
+        let mut offsets = vec![first_offset];
+        while input.peek(Token![->]) {
+            let _arrow: Token![->] = input.parse()?;
+            offsets.push(parse_offset(input)?);
+        }
 
         let expression_string = parse_expr_str(&input, &name, name_span)?;
+        let meta = parse_metadata(&input)?;
 
         Ok(Args {
             name,
             ty,
             base_symbol,
-            offset,
+            offsets,
             expression_string,
+            meta,
             static_name,
         })
     }
 }
+
+/// Sum of terms such as `4 + offset_of!(Type, field)`
+///
+/// Parsed term by term, a whole expression would take the `-` of a following `->` as a minus.
+fn parse_offset(input: ParseStream) -> parse::Result<syn::Expr> {
+    let mut offset = parse_term(input)?;
+    while input.peek(Token![+]) {
+        let _plus: Token![+] = input.parse()?;
+        let term = parse_term(input)?;
+        offset = syn::parse_quote!(#offset + #term);
+    }
+    Ok(offset)
+}
+
+/// An integer, a constant, a macro call such as `offset_of!(Type, field)` or anything in
+/// parentheses
+fn parse_term(input: ParseStream) -> parse::Result<syn::Expr> {
+    if input.peek(syn::LitInt) {
+        Ok(syn::Expr::Lit(input.parse()?))
+    } else if input.peek(syn::token::Paren) {
+        Ok(syn::Expr::Paren(input.parse()?))
+    } else if input.fork().parse::<syn::ExprMacro>().is_ok() {
+        Ok(syn::Expr::Macro(input.parse()?))
+    } else {
+        Ok(syn::Expr::Path(input.parse()?))
+    }
+}
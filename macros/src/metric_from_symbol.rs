@@ -0,0 +1,77 @@
+use probe_plotter_common::{
+    PrimitiveType, ValueType,
+    symbol::{Address, Metadata, Symbol},
+};
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{
+    Token,
+    parse::{self, Parse, ParseStream},
+    parse_macro_input,
+};
+
+use crate::{export, parse_expr_str, parse_metadata, rust_type};
+
+pub fn make_metric_from_symbol(args: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as Args);
+
+    let name = args.static_name.to_string();
+    let (sym_name, record) = export(&Symbol::Metric {
+        ty: ValueType::Primitive(args.ty),
+        name: name.clone(),
+        expr: Some(args.expression_string.value()),
+        address: Address::Symbol { name },
+        meta: args.meta,
+    });
+
+    let ty = rust_type(&syn::Ident::new(&args.ty.to_string(), args.ty_span));
+    let path = args.path;
+    quote! {
+        #record
+
+        const _: () = {
+            // Refers to the static to make sure it exists, has the expected size and is kept by the
+            // linker
+            #[used]
+            #[unsafe(export_name = #sym_name)]
+            static SYMBOL: ::probe_plotter::metric::SymbolRef =
+                ::probe_plotter::metric::SymbolRef::new(&raw const #path, ::core::mem::size_of::<#ty>());
+        };
+    }
+    .into()
+}
+
+// MY_ATOMIC: u32, "MY_ATOMIC * 2"
+// some::module::MY_STATIC: i16 // defaults to "MY_STATIC"
+struct Args {
+    path: syn::Path,
+    static_name: syn::Ident,
+    ty: PrimitiveType,
+    ty_span: proc_macro2::Span,
+    expression_string: syn::LitStr,
+    meta: Metadata,
+}
+
+impl Parse for Args {
+    fn parse(input: ParseStream) -> parse::Result<Self> {
+        let path: syn::Path = input.parse()?;
+        let static_name = path.segments.last().unwrap().ident.clone();
+
+        let _colon: Token![:] = input.parse()?;
+        let ty_span = input.span();
+        let ty = input.parse()?;
+
+        let name = static_name.to_string();
+        let expression_string = parse_expr_str(&input, &name, static_name.span())?;
+        let meta = parse_metadata(&input)?;
+
+        Ok(Args {
+            path,
+            static_name,
+            ty,
+            ty_span,
+            expression_string,
+            meta,
+        })
+    }
+}
@@ -0,0 +1,66 @@
+use probe_plotter_common::symbol::{Metadata, Symbol};
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{
+    Token,
+    parse::{self, Parse, ParseStream},
+    parse_macro_input,
+};
+
+use crate::{export, parse_metadata, parse_name};
+
+pub fn make_metric_group(args: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as Args);
+
+    let (sym_name, record) = export(&Symbol::MetricGroup {
+        name: args.name,
+        ty: args.ty.to_string().as_str().into(),
+        meta: args.meta,
+    });
+
+    let ty = args.ty;
+    let static_name = args.static_name;
+
+    quote!(
+        {
+            #record
+
+            #[used]
+            #[unsafe(export_name = #sym_name)]
+            #[allow(non_upper_case_globals)]
+            static mut #static_name: ::probe_plotter::metric_group::GroupStorage<#ty> =
+                ::probe_plotter::metric_group::GroupStorage::ZERO;
+
+            #[allow(unsafe_code)]
+            unsafe {
+                ::probe_plotter::MetricGroup::<#ty>::take(&raw mut #static_name)
+            }
+        }
+    )
+    .into()
+}
+
+// CURRENTS: Currents
+// CURRENTS: Currents, unit = "A", group = "motor" // see `parse_metadata`
+struct Args {
+    name: String,
+    ty: syn::Ident,
+    meta: Metadata,
+    static_name: syn::Ident,
+}
+
+impl Parse for Args {
+    fn parse(input: ParseStream) -> parse::Result<Self> {
+        let (static_name, name, _name_span) = parse_name(&input)?;
+        let _colon: Token![:] = input.parse()?;
+        let ty = input.parse()?;
+        let meta = parse_metadata(&input)?;
+
+        Ok(Args {
+            name,
+            ty,
+            meta,
+            static_name,
+        })
+    }
+}
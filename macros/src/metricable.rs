@@ -0,0 +1,137 @@
+use probe_plotter_common::{PrimitiveType, symbol::Symbol};
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{DeriveInput, parse_macro_input, spanned::Spanned};
+
+use crate::export;
+
+pub(crate) fn derive_metricable(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match metricable_helper(input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn metricable_helper(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let syn::Data::Enum(data) = &input.data else {
+        return Err(syn::Error::new(
+            input.ident.span(),
+            "Metricable can only be derived for enums",
+        ));
+    };
+
+    let (repr, ty) = parse_repr(&input)?;
+
+    let mut variants = Vec::new();
+    let mut next_discriminant = 0;
+    for variant in &data.variants {
+        if !matches!(variant.fields, syn::Fields::Unit) {
+            return Err(syn::Error::new(
+                variant.span(),
+                "Metricable can only be derived for enums without fields",
+            ));
+        }
+
+        let discriminant = match &variant.discriminant {
+            Some((_eq, e)) => expr_to_i64(e)?,
+            None => next_discriminant,
+        };
+        next_discriminant = discriminant + 1;
+        variants.push((variant.ident.clone(), discriminant));
+    }
+
+    let (sym_name, record) = export(&Symbol::Enum {
+        name: input.ident.to_string(),
+        ty,
+        variants: variants
+            .iter()
+            .map(|(ident, discriminant)| (ident.to_string(), *discriminant))
+            .collect(),
+    });
+
+    let ident = &input.ident;
+    let variant_idents = variants.iter().map(|(ident, _)| ident);
+
+    Ok(quote! {
+        #record
+
+        const _: () = {
+            #[used]
+            #[unsafe(export_name = #sym_name)]
+            static METADATA: u8 = 0;
+        };
+
+        impl ::probe_plotter::metric::Metricable for #ident {
+            type Storage = #repr;
+            const ZERO: #repr = 0;
+
+            unsafe fn store(storage: *mut #repr, x: Self) {
+                unsafe { storage.write_volatile(x as #repr) }
+            }
+
+            unsafe fn load(
+                storage: *const #repr,
+            ) -> ::core::result::Result<Self, ::probe_plotter::metric::LoadError> {
+                match unsafe { storage.read_volatile() } {
+                    #(x if x == Self::#variant_idents as #repr => Ok(Self::#variant_idents),)*
+                    _ => Err(::probe_plotter::metric::LoadError::Invalid),
+                }
+            }
+        }
+    })
+}
+
+/// Find the integer type in `#[repr(...)]`
+fn parse_repr(input: &DeriveInput) -> syn::Result<(syn::Ident, PrimitiveType)> {
+    let mut repr = None;
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("repr")) {
+        attr.parse_nested_meta(|meta| {
+            if let Some(ident) = meta.path.get_ident()
+                && let Ok(ty) = PrimitiveType::try_from(ident.to_string().as_str())
+            {
+                repr = Some((ident.clone(), ty));
+            }
+
+            // Skip arguments such as the `4` in `align(4)`
+            if meta.input.peek(syn::token::Paren) {
+                let _content;
+                syn::parenthesized!(_content in meta.input);
+            }
+            Ok(())
+        })?;
+    }
+
+    match repr {
+        Some((
+            ident,
+            ty @ (PrimitiveType::u8
+            | PrimitiveType::u16
+            | PrimitiveType::u32
+            | PrimitiveType::i8
+            | PrimitiveType::i16
+            | PrimitiveType::i32),
+        )) => Ok((ident, ty)),
+        _ => Err(syn::Error::new(
+            input.ident.span(),
+            "Metricable requires one of #[repr(u8)], #[repr(u16)], #[repr(u32)], #[repr(i8)], #[repr(i16)] or #[repr(i32)]",
+        )),
+    }
+}
+
+fn expr_to_i64(e: &syn::Expr) -> syn::Result<i64> {
+    let error_msg = "expected int literal";
+    match e {
+        syn::Expr::Lit(syn::ExprLit {
+            lit: syn::Lit::Int(i),
+            ..
+        }) => i.base10_parse(),
+        syn::Expr::Unary(syn::ExprUnary {
+            op: syn::UnOp::Neg(_),
+            expr,
+            ..
+        }) => Ok(-expr_to_i64(expr)?),
+        x => Err(syn::Error::new(x.span(), error_msg)),
+    }
+}
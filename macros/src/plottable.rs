@@ -0,0 +1,91 @@
+use probe_plotter_common::{ValueType, symbol::Symbol};
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{DeriveInput, parse_macro_input, spanned::Spanned};
+
+use crate::export;
+
+pub(crate) fn derive_plottable(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match plottable_helper(input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn plottable_helper(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let syn::Data::Struct(data) = &input.data else {
+        return Err(syn::Error::new(
+            input.ident.span(),
+            "Plottable can only be derived for structs",
+        ));
+    };
+
+    let syn::Fields::Named(named_fields) = &data.fields else {
+        return Err(syn::Error::new(
+            input.ident.span(),
+            "Plottable can only be derived for structs with named fields",
+        ));
+    };
+
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new(
+            input.generics.params.first().unwrap().span(),
+            "Plottable can not be derived for generic structs",
+        ));
+    }
+
+    // Only fields with a plain type name such as `i32`, `Q15` or the name of another type with
+    // `#[derive(Plottable)]` or `#[derive(Metricable)]` are plotted, anything else is skipped
+    let fields: Vec<_> = named_fields
+        .named
+        .iter()
+        .filter_map(|field| match &field.ty {
+            syn::Type::Path(p) if p.qself.is_none() => {
+                Some((field.ident.as_ref()?, p.path.get_ident()?))
+            }
+            _ => None,
+        })
+        .collect();
+
+    let (sym_name, record) = export(&Symbol::Struct {
+        name: input.ident.to_string(),
+        fields: fields
+            .iter()
+            .map(|(ident, ty)| (ident.to_string(), ValueType::from(ty.to_string().as_str())))
+            .collect(),
+    });
+
+    let ident = &input.ident;
+    let field_idents = fields.iter().map(|(ident, _)| ident);
+    let field_count = fields.len();
+
+    Ok(quote! {
+        #record
+
+        const _: () = {
+            #[used]
+            #[unsafe(export_name = #sym_name)]
+            static METADATA: [u32; #field_count] = [
+                #(::core::mem::offset_of!(#ident, #field_idents) as u32,)*
+            ];
+        };
+
+        impl ::probe_plotter::metric::Metricable for #ident {
+            type Storage = ::core::mem::MaybeUninit<Self>;
+            const ZERO: Self::Storage = ::core::mem::MaybeUninit::zeroed();
+
+            unsafe fn store(storage: *mut Self::Storage, x: Self) {
+                unsafe { storage.write_volatile(::core::mem::MaybeUninit::new(x)) }
+            }
+
+            unsafe fn load(
+                storage: *const Self::Storage,
+            ) -> ::core::result::Result<Self, ::probe_plotter::metric::LoadError> {
+                // The storage is always initialized before a `Metric` is handed out
+                Ok(unsafe { storage.read_volatile().assume_init() })
+            }
+        }
+    })
+}
@@ -19,10 +19,11 @@ pub fn make_ptr(args: TokenStream) -> TokenStream {
         lit: syn::Lit::Int(LitInt::new("0", Span::call_site())),
     });
     metric_helper(metric::Args {
-        ty: Ident::new("u32", Span::call_site()),
+        ty: Ident::new("usize", Span::call_site()),
         name: args.name.to_string(),
         initial_val: zero,
         expression_string: None,
+        meta: Default::default(),
         static_name: args.static_name,
     })
 }
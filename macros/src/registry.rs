@@ -0,0 +1,52 @@
+use probe_plotter_common::symbol::Symbol;
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{
+    Token,
+    parse::{self, Parse, ParseStream},
+    parse_macro_input,
+};
+
+use crate::export;
+
+pub fn make_registry(args: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as Args);
+
+    let (sym_name, record) = export(&Symbol::Registry {
+        name: args.static_name.to_string(),
+        capacity: args.capacity,
+    });
+
+    let static_name = args.static_name;
+    let capacity = proc_macro2::Literal::u64_unsuffixed(args.capacity);
+
+    quote! {
+        #record
+
+        #[used]
+        #[unsafe(export_name = #sym_name)]
+        static #static_name: ::probe_plotter::registry::Registry<#capacity> =
+            ::probe_plotter::registry::Registry::new();
+    }
+    .into()
+}
+
+// REGISTRY: 16
+struct Args {
+    static_name: syn::Ident,
+    capacity: u64,
+}
+
+impl Parse for Args {
+    fn parse(input: ParseStream) -> parse::Result<Self> {
+        let static_name = input.parse()?;
+        let _colon: Token![:] = input.parse()?;
+        let capacity: syn::LitInt = input.parse()?;
+        let capacity = capacity.base10_parse()?;
+
+        Ok(Args {
+            static_name,
+            capacity,
+        })
+    }
+}
@@ -1,64 +1,227 @@
-use probe_plotter_common::symbol::Symbol;
+use probe_plotter_common::{
+    PrimitiveType,
+    symbol::{Address, Bits, Metadata, Symbol},
+};
 use proc_macro::TokenStream;
 use quote::quote;
+use std::ops::RangeInclusive;
 use syn::{
     RangeLimits, Token,
     parse::{self, Parse, ParseStream},
     parse_macro_input,
 };
 
-use crate::{expr_to_float_lit, parse_name};
+use crate::{
+    at_metadata, export, expr_to_float_lit, parse_bits, parse_metadata, parse_name, rust_type,
+};
 
 pub(crate) fn make_setting(args: TokenStream) -> TokenStream {
     let args = parse_macro_input!(args as SettingArgs);
+    let (range, step_size) = match args.validate() {
+        Ok(x) => x,
+        // Only valid in expression position within a block
+        Err(e) => return quote!({ #(#e)* }).into(),
+    };
 
-    let sym_name = serde_json::to_string(&Symbol::Setting {
-        ty: args.ty.to_string().as_str().try_into().unwrap(),
-        name: args.name.to_string(),
-        range: args.range_start.base10_parse().unwrap()..=args.range_end.base10_parse().unwrap(),
-        step_size: args.step_size.base10_parse().unwrap(),
-    })
-    .unwrap();
-
-    let ty = args.ty;
+    let (sym_name, record) = args.export(Address::Symbols, range, step_size);
+    let ty = rust_type(&args.ty);
     let initial_value = args.initial_val;
     let static_name = args.static_name;
 
     quote!(
-        cortex_m::interrupt::free(|_| {
+        {
+            #record
+
             #[used]
             #[unsafe(export_name = #sym_name)]
             #[allow(non_upper_case_globals)]
-            static mut #static_name: (#ty, bool) =
-                (0 as _, false);
+            static mut #static_name: (::probe_plotter::setting::SettingStorage<#ty>, bool) =
+                (::probe_plotter::setting::SettingStorage::<#ty>::ZERO, false);
 
             #[allow(unsafe_code)]
-            let used = unsafe { #static_name.1 };
+            let used = unsafe { !::probe_plotter::sync::take(&raw mut #static_name.1) };
             if used {
                 None
             } else {
                 #[allow(unsafe_code)]
                 unsafe {
-                    #static_name.1 = true;
-                    #static_name.0 = #initial_value;
-                    Some(::probe_plotter::Setting::new(&mut #static_name.0))
+                    let initial_value: #ty = #initial_value;
+                    let storage = &raw mut #static_name.0;
+                    ::probe_plotter::setting::SettingStorage::store(storage, initial_value);
+                    Some(::probe_plotter::Setting::<#ty>::new(storage, initial_value))
                 }
             }
-        })
+        }
     )
     .into()
 }
 
-// root.child.leaf: i8 @ 0x1234, setting
+pub(crate) fn make_setting_from_address(args: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args with SettingArgs::parse_from_address);
+    let (range, step_size) = match args.validate() {
+        Ok(x) => x,
+        Err(e) => return quote!(#(#e)*).into(),
+    };
+
+    let address = Address::Hardcoded {
+        address: args.address,
+        bits: args.bits,
+    };
+    let (sym_name, record) = args.export(address, range, step_size);
+    let static_name = args.static_name;
+    quote! {
+        #record
+
+        #[used]
+        #[unsafe(export_name = #sym_name)]
+        #[allow(non_upper_case_globals)]
+        static #static_name: u8 = 0;
+    }
+    .into()
+}
+
+impl SettingArgs {
+    /// `range` and `step_size` as returned by [Self::validate]
+    fn export(
+        &self,
+        address: Address,
+        range: RangeInclusive<f64>,
+        step_size: f64,
+    ) -> (String, proc_macro2::TokenStream) {
+        export(&Symbol::Setting {
+            ty: self.ty.to_string().as_str().into(),
+            name: self.name.to_string(),
+            range,
+            step_size,
+            address,
+            meta: self.meta.clone(),
+        })
+    }
+
+    /// Check that the range fits the type, the initial value is within the range and the step size
+    /// is positive, returning the range and step size
+    ///
+    /// Returns one error per problem found, to be reported by one `compile_error!` each
+    fn validate(&self) -> Result<(RangeInclusive<f64>, f64), Vec<proc_macro2::TokenStream>> {
+        let mut errors = Vec::new();
+        let mut error = |span, message: String| errors.push(syn::Error::new(span, message));
+
+        let parse = |lit: &syn::LitFloat| {
+            lit.base10_parse::<f64>()
+                .map_err(|e| vec![e.to_compile_error()])
+        };
+        let start = parse(&self.range_start)?;
+        let end = parse(&self.range_end)?;
+        let step = parse(&self.step_size)?;
+
+        if start > end {
+            error(
+                self.range_start.span(),
+                format!("The start of the range, {start}, is larger than the end, {end}"),
+            );
+        }
+        if step <= 0.0 {
+            error(
+                self.step_size.span(),
+                format!("The step size must be positive, got {step}"),
+            );
+        }
+
+        // Enums are checked by the compiler, the host looks up the range of valid values
+        if let Ok(ty) = PrimitiveType::try_from(self.ty.to_string().as_str()) {
+            let type_range = ty.range();
+            if start < *type_range.start() {
+                error(
+                    self.range_start.span(),
+                    format!(
+                        "{start} is below the smallest value of {ty}, {}",
+                        type_range.start()
+                    ),
+                );
+            }
+            if end > *type_range.end() {
+                error(
+                    self.range_end.span(),
+                    format!(
+                        "{end} is above the largest value of {ty}, {}",
+                        type_range.end()
+                    ),
+                );
+            }
+
+            let is_integer = !matches!(
+                ty,
+                PrimitiveType::f32 | PrimitiveType::f64 | PrimitiveType::Q { .. }
+            );
+            if is_integer {
+                for (x, lit) in [
+                    (start, &self.range_start),
+                    (end, &self.range_end),
+                    (step, &self.step_size),
+                ] {
+                    if x.fract() != 0.0 {
+                        error(
+                            lit.span(),
+                            format!("{x} is not a whole number as {ty} requires"),
+                        );
+                    }
+                }
+            }
+        }
+
+        if let Some(bits) = self.bits
+            && end > bits.max() as f64
+        {
+            error(
+                self.range_end.span(),
+                format!(
+                    "{end} does not fit in bits [{}:{}], the largest value is {}",
+                    bits.high,
+                    bits.low,
+                    bits.max()
+                ),
+            );
+        }
+
+        // The initial value can only be checked if it is a number literal
+        if let Some(initial_val) = &self.initial_val
+            && let Ok(initial) = expr_to_float_lit(initial_val.clone())
+            && let Ok(x) = initial.base10_parse::<f64>()
+            && !(start..=end).contains(&x)
+        {
+            error(
+                initial.span(),
+                format!("The initial value, {x}, is outside of the range {start}..={end}"),
+            );
+        }
+
+        if errors.is_empty() {
+            Ok((start..=end, step))
+        } else {
+            Err(errors.iter().map(syn::Error::to_compile_error).collect())
+        }
+    }
+}
 
 // FOO: i32 = 0, 0..=10, 2
-// FOO: i32 = 0, 0..=10, // Step size defaults to 1
+// FOO: i32 = 0, 0..=10 // Step size defaults to 1, 0.01 for floats and the resolution of fixed point types
 // FOO: i32 = 0 // range defaults to the types full range
-// TODO Implement the defaults
+// FOO: bool = false // range is always 0..=1 with step size 1
+// FOO: MyEnum = MyEnum::Bar // any variant of an enum with `#[derive(Metricable)]`
+// TIM1_CCR1: u16 @ 0x40012C34, 0..=1000, 1 // for make_setting_from_address, no initial value
+// TIM1_PSC.low: u16 @ 0x40012C28 [7:0] // only bits 7 to 0, range defaults to 0..=255
+// FOO: f32 = 0.5, 0.0..=1.0, unit = "V", description = "Output voltage" // see `parse_metadata`
 pub(crate) struct SettingArgs {
     pub(crate) name: String,
     pub(crate) ty: syn::Ident,
-    pub(crate) initial_val: syn::Expr,
+
+    /// `None` for make_setting_from_address
+    pub(crate) initial_val: Option<syn::Expr>,
+
+    /// Only used by make_setting_from_address
+    pub(crate) address: u64,
+    pub(crate) bits: Option<Bits>,
+    pub(crate) meta: Metadata,
     pub(crate) range_start: syn::LitFloat,
     pub(crate) range_end: syn::LitFloat,
     pub(crate) step_size: syn::LitFloat,
@@ -67,32 +230,90 @@ pub(crate) struct SettingArgs {
 
 impl Parse for SettingArgs {
     fn parse(input: ParseStream) -> parse::Result<Self> {
+        Self::parse_args(input, false)
+    }
+}
+
+impl SettingArgs {
+    fn parse_from_address(input: ParseStream) -> parse::Result<Self> {
+        Self::parse_args(input, true)
+    }
+
+    fn parse_args(input: ParseStream, from_address: bool) -> parse::Result<Self> {
         let (static_name, name, _name_span) = parse_name(&input)?;
 
         let _colon: Token![:] = input.parse()?;
-        let ty = input.parse()?;
-        let _eq: Token![=] = input.parse()?;
-        let initial_val = input.parse()?;
+        let ty: syn::Ident = input.parse()?;
+        let (initial_val, address, bits) = if from_address {
+            // The value is whatever is in the register
+            let _at: Token![@] = input.parse()?;
+            let address: syn::LitInt = input.parse()?;
+            let bits = parse_bits(&input, &ty.to_string(), ty.span())?;
+            (None, address.base10_parse()?, bits)
+        } else {
+            let _eq: Token![=] = input.parse()?;
+            (Some(input.parse()?), 0, None)
+        };
+
+        // An enum can only be one of its variants, which the host looks up, so there is no need
+        // for a range
+        let primitive_type = PrimitiveType::try_from(ty.to_string().as_str());
+        let (default_range, default_step) = match (bits, primitive_type) {
+            (Some(bits), _) => (0.0..=bits.max() as f64, 1.0),
+            (None, Ok(ty)) => (ty.range(), ty.default_step()),
+            (None, Err(())) => (0.0..=0.0, 1.0),
+        };
+        let span = ty.span();
+        let float_lit = |x: f64| syn::LitFloat::new(&format!("{x:?}"), span);
+
+        let mut args = Self {
+            name,
+            ty,
+            initial_val,
+            address,
+            bits,
+            range_start: float_lit(*default_range.start()),
+            range_end: float_lit(*default_range.end()),
+            step_size: float_lit(default_step),
+            meta: Metadata::default(),
+            static_name,
+        };
+        if at_metadata(&input) {
+            args.meta = parse_metadata(&input)?;
+            return Ok(args);
+        }
 
         let _comma: parse::Result<Token![,]> = input.parse();
         let range: syn::Expr = input.parse()?;
 
         let syn::Expr::Range(range) = range else {
-            panic!("Invalid range")
+            return Err(syn::Error::new_spanned(
+                range,
+                "Expected a range such as 0..=10",
+            ));
         };
 
-        let range_start = range
-            .start
-            .expect("Only inclusive ranges with both a start and end are supported");
-        let range_end = range
-            .end
-            .expect("Only inclusive ranges with both a start and end are supported");
-        assert!(
-            matches!(range.limits, RangeLimits::Closed(_)),
-            "Only inclusive ranges with both a start and end are supported"
-        );
+        let (Some(range_start), Some(range_end), RangeLimits::Closed(_)) =
+            (range.start.clone(), range.end.clone(), range.limits)
+        else {
+            return Err(syn::Error::new_spanned(
+                range,
+                "Only inclusive ranges with both a start and end are supported",
+            ));
+        };
+
+        args.range_start = expr_to_float_lit(*range_start)?;
+        args.range_end = expr_to_float_lit(*range_end)?;
 
+        if at_metadata(&input) {
+            args.meta = parse_metadata(&input)?;
+            return Ok(args);
+        }
         let _comma: parse::Result<Token![,]> = input.parse();
+        if input.is_empty() {
+            return Ok(args);
+        }
+
         let step_size: syn::Lit = input.parse()?;
 
         let step_size = match step_size {
@@ -101,14 +322,9 @@ impl Parse for SettingArgs {
             x => return Err(syn::Error::new(x.span(), "expected float or int literal")),
         };
 
-        Ok(Self {
-            name,
-            ty,
-            initial_val,
-            range_start: expr_to_float_lit(*range_start)?,
-            range_end: expr_to_float_lit(*range_end)?,
-            step_size,
-            static_name,
-        })
+        args.step_size = step_size;
+        args.meta = parse_metadata(&input)?;
+
+        Ok(args)
     }
 }
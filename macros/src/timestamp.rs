@@ -0,0 +1,47 @@
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{
+    Token,
+    parse::{self, Parse, ParseStream},
+    parse_macro_input,
+};
+
+pub fn timestamp(args: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as Args);
+    let frequency = args.frequency;
+    let timestamp = args.timestamp;
+
+    quote! {
+        const _: () = {
+            // Read by the host to convert the timestamps to seconds
+            #[used]
+            #[unsafe(export_name = "probe_plotter.timestamp_frequency")]
+            static FREQUENCY: u32 = #frequency;
+
+            #[unsafe(export_name = "_probe_plotter_timestamp")]
+            fn timestamp() -> u32 {
+                #timestamp
+            }
+        };
+    }
+    .into()
+}
+
+// 170_000_000, cortex_m::peripheral::DWT::cycle_count()
+struct Args {
+    frequency: syn::Expr,
+    timestamp: syn::Expr,
+}
+
+impl Parse for Args {
+    fn parse(input: ParseStream) -> parse::Result<Self> {
+        let frequency = input.parse()?;
+        let _comma: Token![,] = input.parse()?;
+        let timestamp = input.parse()?;
+
+        Ok(Args {
+            frequency,
+            timestamp,
+        })
+    }
+}
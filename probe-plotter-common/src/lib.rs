@@ -1,7 +1,8 @@
-use std::fmt::Display;
+use std::{fmt::Display, ops::RangeInclusive};
 
 use syn::parse::Parse;
 
+pub mod record;
 pub mod symbol;
 
 #[allow(non_camel_case_types)]
@@ -10,10 +11,22 @@ pub enum PrimitiveType {
     u8,
     u16,
     u32,
+    u64,
     i8,
     i16,
     i32,
+    i64,
     f32,
+    f64,
+    bool,
+
+    /// Signed fixed point number with `int` integer bits and `frac` fractional bits, Qm.n in
+    /// the usual notation. The value is stored as an i8, i16 or i32 depending on the total number
+    /// of bits including the sign bit. Written as `Q15` for Q0.15 or `Q3_12` for Q3.12 in macros.
+    Q {
+        int: u8,
+        frac: u8,
+    },
 }
 
 impl Display for PrimitiveType {
@@ -22,10 +35,16 @@ impl Display for PrimitiveType {
             PrimitiveType::u8 => write!(f, "u8"),
             PrimitiveType::u16 => write!(f, "u16"),
             PrimitiveType::u32 => write!(f, "u32"),
+            PrimitiveType::u64 => write!(f, "u64"),
             PrimitiveType::i8 => write!(f, "i8"),
             PrimitiveType::i16 => write!(f, "i16"),
             PrimitiveType::i32 => write!(f, "i32"),
+            PrimitiveType::i64 => write!(f, "i64"),
             PrimitiveType::f32 => write!(f, "f32"),
+            PrimitiveType::f64 => write!(f, "f64"),
+            PrimitiveType::bool => write!(f, "bool"),
+            PrimitiveType::Q { int: 0, frac } => write!(f, "Q{frac}"),
+            PrimitiveType::Q { int, frac } => write!(f, "Q{int}_{frac}"),
         }
     }
 }
@@ -38,11 +57,101 @@ impl TryFrom<&str> for PrimitiveType {
             "u8" => Ok(PrimitiveType::u8),
             "u16" => Ok(PrimitiveType::u16),
             "u32" => Ok(PrimitiveType::u32),
+            "u64" => Ok(PrimitiveType::u64),
             "i8" => Ok(PrimitiveType::i8),
             "i16" => Ok(PrimitiveType::i16),
             "i32" => Ok(PrimitiveType::i32),
+            "i64" => Ok(PrimitiveType::i64),
             "f32" => Ok(PrimitiveType::f32),
-            _ => Err(()),
+            "f64" => Ok(PrimitiveType::f64),
+            "bool" => Ok(PrimitiveType::bool),
+            _ => parse_fixed_point(value).ok_or(()),
+        }
+    }
+}
+
+/// `Q15` is Q0.15 and `Q3_12` is Q3.12
+fn parse_fixed_point(value: &str) -> Option<PrimitiveType> {
+    let value = value.strip_prefix('Q')?;
+    let (int, frac) = match value.split_once('_') {
+        Some((int, frac)) => (int.parse().ok()?, frac.parse().ok()?),
+        None => (0, value.parse().ok()?),
+    };
+
+    let ty = PrimitiveType::Q { int, frac };
+    ty.fixed_point_storage().map(|_| ty)
+}
+
+impl PrimitiveType {
+    /// 64 bit types are wider than the word size of 32 bit targets. On these they are stored
+    /// together with a pair of sequence counters to allow for tear-free reads, see
+    /// `probe_plotter::metric::Wide`
+    pub fn is_wide(&self) -> bool {
+        matches!(
+            self,
+            PrimitiveType::u64 | PrimitiveType::i64 | PrimitiveType::f64
+        )
+    }
+
+    /// Number of bytes used to store a value of this type on the target
+    pub fn storage_size(&self) -> u64 {
+        match self {
+            PrimitiveType::u8 | PrimitiveType::i8 | PrimitiveType::bool => 1,
+            PrimitiveType::u16 | PrimitiveType::i16 => 2,
+            PrimitiveType::u32 | PrimitiveType::i32 | PrimitiveType::f32 => 4,
+
+            // See `is_wide`, this is the size on 32 bit targets
+            PrimitiveType::u64 | PrimitiveType::i64 | PrimitiveType::f64 => 16,
+
+            PrimitiveType::Q { .. } => self.fixed_point_storage().map_or(0, |s| s.storage_size()),
+        }
+    }
+
+    /// Every value the type can represent, the default range of settings
+    pub fn range(&self) -> RangeInclusive<f64> {
+        match self {
+            PrimitiveType::u8 => u8::MIN as f64..=u8::MAX as f64,
+            PrimitiveType::u16 => u16::MIN as f64..=u16::MAX as f64,
+            PrimitiveType::u32 => u32::MIN as f64..=u32::MAX as f64,
+            PrimitiveType::u64 => u64::MIN as f64..=u64::MAX as f64,
+            PrimitiveType::i8 => i8::MIN as f64..=i8::MAX as f64,
+            PrimitiveType::i16 => i16::MIN as f64..=i16::MAX as f64,
+            PrimitiveType::i32 => i32::MIN as f64..=i32::MAX as f64,
+            PrimitiveType::i64 => i64::MIN as f64..=i64::MAX as f64,
+            PrimitiveType::f32 => f32::MIN as f64..=f32::MAX as f64,
+            PrimitiveType::f64 => f64::MIN..=f64::MAX,
+            PrimitiveType::bool => 0.0..=1.0,
+            PrimitiveType::Q { int, .. } => {
+                let step = self.default_step();
+                let max = 2f64.powi((*int).into());
+                -max..=max - step
+            }
+        }
+    }
+
+    /// The default step size of settings, the smallest difference between two values for integer
+    /// and fixed point types
+    pub fn default_step(&self) -> f64 {
+        match self {
+            // Any value would be arbitrary, this gives two decimals which suits most tuning knobs
+            PrimitiveType::f32 | PrimitiveType::f64 => 0.01,
+            PrimitiveType::Q { frac, .. } => 2f64.powi(-i32::from(*frac)),
+            _ => 1.0,
+        }
+    }
+
+    /// The integer type used to store a fixed point number, `None` for any other type or if
+    /// the number of bits does not add up to 8, 16 or 32
+    pub fn fixed_point_storage(&self) -> Option<PrimitiveType> {
+        let PrimitiveType::Q { int, frac } = self else {
+            return None;
+        };
+
+        match 1 + *int as u32 + *frac as u32 {
+            8 => Some(PrimitiveType::i8),
+            16 => Some(PrimitiveType::i16),
+            32 => Some(PrimitiveType::i32),
+            _ => None,
         }
     }
 }
@@ -53,12 +162,59 @@ impl Parse for PrimitiveType {
         ident.to_string().as_str().try_into().map_err(|()| {
             syn::Error::new(
                 ident.span(),
-                "Expected one of u8, u16, u32, i8, i16, i32 or f32",
+                "Expected one of u8, u16, u32, u64, i8, i16, i32, i64, f32, f64, bool or a fixed point type such as Q15 or Q3_12",
             )
         })
     }
 }
 
+/// Type of a metric or setting as written in the macro invocation
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum ValueType {
+    Primitive(PrimitiveType),
+
+    /// Name of an enum with `#[derive(Metricable)]` or a struct with `#[derive(Plottable)]`,
+    /// see [symbol::Symbol::Enum] and [symbol::Symbol::Struct]
+    Named(String),
+}
+
+impl Display for ValueType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValueType::Primitive(ty) => write!(f, "{ty}"),
+            ValueType::Named(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+impl From<&str> for ValueType {
+    /// Anything which is not a primitive type is assumed to be an enum or struct
+    fn from(value: &str) -> Self {
+        match PrimitiveType::try_from(value) {
+            Ok(ty) => ValueType::Primitive(ty),
+            Err(()) => ValueType::Named(value.to_owned()),
+        }
+    }
+}
+
+/// Parse and test evaluate the expression of a metric, the same way the host does
+///
+/// The value is referred to by `variable`, which is the name of the metric with `.` replaced by
+/// `__`, see [strip_dots]. Used by the macros to report errors at compile time.
+pub fn parse_expr(expr: &str, variable: &str) -> Result<shunting::RPNExpr, String> {
+    let parsed = shunting::ShuntingParser::parse_str(expr)
+        .map_err(|e| format!("Failed to parse expression `{expr}`: {e}"))?;
+
+    let math_ctx = shunting::MathContext::new();
+    math_ctx.setvar(variable, shunting::MathOp::Number(0.0));
+    math_ctx.eval(&parsed).map_err(|e| {
+        format!("Failed to evaluate expression `{expr}`: {e}. Use `{variable}` as the name for the value in the expression")
+    })?;
+
+    Ok(parsed)
+}
+
 pub fn strip_dots(s: &str) -> String {
     s.replace('.', "__")
 }
@@ -0,0 +1,424 @@
+//! Compact encoding of [Symbol]s in the `.probe_plotter` section
+//!
+//! Older versions encoded the [Symbol] as JSON in the name of the static, see [Symbol::demangle].
+//! Now the static gets a short name, see [Symbol::export_name], and the [Symbol] is put in a
+//! record of its own in the `.probe_plotter` section.
+//!
+//! A record is the format version, the length of the value as a varint followed by the value. The
+//! value is the same as the JSON form but binary, see [tag]. The records are byte arrays so the
+//! linker places them right after each other, any zero bytes between them are skipped.
+
+use serde_json::{Map, Number, Value};
+
+use crate::symbol::Symbol;
+
+/// Name of the linker section with the records
+pub const SECTION: &str = ".probe_plotter";
+
+/// Version of the metadata format, bumped on incompatible changes to the records or to [Symbol]
+///
+/// Every record starts with it, and `probe-plotter` has it in the static [VERSION_SYMBOL] so the
+/// host can tell whether it understands the firmware before reading any record.
+pub const VERSION: u8 = 1;
+
+/// Name of the static with [VERSION] as a `u32`, firmware built before it was added uses the JSON
+/// symbol names
+pub const VERSION_SYMBOL: &str = "probe_plotter.version";
+
+/// Prefix of [Symbol::export_name]
+pub const EXPORT_PREFIX: &str = "probe_plotter.";
+
+/// First byte of every value
+mod tag {
+    pub const NULL: u8 = 0;
+    pub const FALSE: u8 = 1;
+    pub const TRUE: u8 = 2;
+    /// Varint
+    pub const U64: u8 = 3;
+    /// Zigzag varint
+    pub const I64: u8 = 4;
+    /// Little endian
+    pub const F64: u8 = 5;
+    /// Varint length followed by utf-8
+    pub const STRING: u8 = 6;
+    /// Varint length followed by the values
+    pub const ARRAY: u8 = 7;
+    /// Varint length followed by the keys, without tag, and values
+    pub const OBJECT: u8 = 8;
+}
+
+#[derive(Debug)]
+pub enum RecordError {
+    /// Written by a newer version of probe-plotter
+    UnsupportedVersion(u8),
+    Malformed,
+    InvalidSymbol(#[allow(dead_code)] serde_json::Error),
+}
+
+/// Encode `symbol` as a record
+pub fn encode(symbol: &Symbol) -> Vec<u8> {
+    let mut value = Vec::new();
+    encode_value(&mut value, &serde_json::to_value(symbol).unwrap());
+
+    let mut out = vec![VERSION];
+    encode_varint(&mut out, value.len() as u64);
+    out.extend(value);
+    out
+}
+
+/// Decode all records of a `.probe_plotter` section
+///
+/// Decoding stops at the first record which can not be skipped
+pub fn decode_all(mut data: &[u8]) -> Vec<Result<Symbol, RecordError>> {
+    let mut symbols = Vec::new();
+    while let Some((&version, rest)) = data.split_first() {
+        data = rest;
+        match version {
+            0 => continue,
+            VERSION => {}
+            _ => {
+                symbols.push(Err(RecordError::UnsupportedVersion(version)));
+                break;
+            }
+        }
+
+        // `decode_len` makes sure the record is within `data`
+        let Some(len) = decode_len(&mut data) else {
+            symbols.push(Err(RecordError::Malformed));
+            break;
+        };
+        let (mut record, rest) = data.split_at(len);
+        data = rest;
+
+        symbols.push(match decode_value(&mut record) {
+            Some(value) if record.is_empty() => {
+                serde_json::from_value(value).map_err(RecordError::InvalidSymbol)
+            }
+            _ => Err(RecordError::Malformed),
+        });
+    }
+    symbols
+}
+
+fn encode_varint(out: &mut Vec<u8>, mut x: u64) {
+    while x >= 0x80 {
+        out.push(x as u8 | 0x80);
+        x >>= 7;
+    }
+    out.push(x as u8);
+}
+
+fn encode_str(out: &mut Vec<u8>, s: &str) {
+    encode_varint(out, s.len() as u64);
+    out.extend(s.as_bytes());
+}
+
+fn encode_value(out: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Null => out.push(tag::NULL),
+        Value::Bool(false) => out.push(tag::FALSE),
+        Value::Bool(true) => out.push(tag::TRUE),
+        Value::Number(x) => {
+            if let Some(x) = x.as_u64() {
+                out.push(tag::U64);
+                encode_varint(out, x);
+            } else if let Some(x) = x.as_i64() {
+                out.push(tag::I64);
+                encode_varint(out, ((x << 1) ^ (x >> 63)) as u64);
+            } else {
+                out.push(tag::F64);
+                out.extend(x.as_f64().unwrap().to_le_bytes());
+            }
+        }
+        Value::String(s) => {
+            out.push(tag::STRING);
+            encode_str(out, s);
+        }
+        Value::Array(values) => {
+            out.push(tag::ARRAY);
+            encode_varint(out, values.len() as u64);
+            for value in values {
+                encode_value(out, value);
+            }
+        }
+        Value::Object(map) => {
+            out.push(tag::OBJECT);
+            encode_varint(out, map.len() as u64);
+            for (key, value) in map {
+                encode_str(out, key);
+                encode_value(out, value);
+            }
+        }
+    }
+}
+
+fn take<'a>(data: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    let (bytes, rest) = data.split_at_checked(len)?;
+    *data = rest;
+    Some(bytes)
+}
+
+fn decode_varint(data: &mut &[u8]) -> Option<u64> {
+    let mut x = 0;
+    for shift in (0..64).step_by(7) {
+        let byte = take(data, 1)?[0];
+        x |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Some(x);
+        }
+    }
+    None
+}
+
+fn decode_len(data: &mut &[u8]) -> Option<usize> {
+    let len = decode_varint(data)?.try_into().ok()?;
+    // Every item is at least one byte, so longer lengths are malformed
+    (len <= data.len()).then_some(len)
+}
+
+fn decode_str(data: &mut &[u8]) -> Option<String> {
+    let len = decode_len(data)?;
+    String::from_utf8(take(data, len)?.to_vec()).ok()
+}
+
+fn decode_value(data: &mut &[u8]) -> Option<Value> {
+    let tag = take(data, 1)?[0];
+    Some(match tag {
+        tag::NULL => Value::Null,
+        tag::FALSE => Value::Bool(false),
+        tag::TRUE => Value::Bool(true),
+        tag::U64 => decode_varint(data)?.into(),
+        tag::I64 => {
+            let x = decode_varint(data)?;
+            (((x >> 1) as i64) ^ -((x & 1) as i64)).into()
+        }
+        tag::F64 => {
+            let x = f64::from_le_bytes(take(data, 8)?.try_into().unwrap());
+            Value::Number(Number::from_f64(x)?)
+        }
+        tag::STRING => Value::String(decode_str(data)?),
+        tag::ARRAY => {
+            let len = decode_len(data)?;
+            Value::Array(
+                (0..len)
+                    .map(|_| decode_value(data))
+                    .collect::<Option<_>>()?,
+            )
+        }
+        tag::OBJECT => {
+            let len = decode_len(data)?;
+            let mut map = Map::new();
+            for _ in 0..len {
+                let key = decode_str(data)?;
+                map.insert(key, decode_value(data)?);
+            }
+            Value::Object(map)
+        }
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        PrimitiveType,
+        symbol::{Address, Bits, Metadata},
+    };
+
+    fn meta() -> Metadata {
+        Metadata {
+            unit: Some("mA".to_owned()),
+            description: Some("Current limit".to_owned()),
+            color: Some("#ff8000".to_owned()),
+            group: Some("motor".to_owned()),
+        }
+    }
+
+    fn symbols() -> Vec<Symbol> {
+        vec![
+            Symbol::Metric {
+                name: "FOO".to_owned(),
+                expr: Some("FOO * 3.0".to_owned()),
+                ty: "i32".into(),
+                address: Address::Symbols,
+                meta: Metadata::default(),
+            },
+            Symbol::Metric {
+                name: "DWT_CTRL.NUMCOMP".to_owned(),
+                expr: None,
+                ty: "u32".into(),
+                address: Address::Hardcoded {
+                    address: 0xE0001000,
+                    bits: Some(Bits { high: 31, low: 28 }),
+                },
+                meta: meta(),
+            },
+            Symbol::Metric {
+                name: "MY_ATOMIC".to_owned(),
+                expr: None,
+                ty: "Q3_12".into(),
+                address: Address::Symbol {
+                    name: "MY_ATOMIC".to_owned(),
+                },
+                meta: Metadata::default(),
+            },
+            Symbol::Metric {
+                name: "root.child".to_owned(),
+                expr: None,
+                ty: "u8".into(),
+                address: Address::RelativeBaseMetricWithOffset {
+                    base_metric: "BASE".to_owned(),
+                    offset: u64::MAX,
+                },
+                meta: Metadata::default(),
+            },
+            Symbol::Metric {
+                name: "motor.current".to_owned(),
+                expr: None,
+                ty: "i32".into(),
+                address: Address::PointerChain {
+                    base_metric: "CTX".to_owned(),
+                    len: 2,
+                },
+                meta: Metadata::default(),
+            },
+            Symbol::MetricArray {
+                name: "WAVEFORM".to_owned(),
+                expr: Some("WAVEFORM / 2".to_owned()),
+                ty: "i16".into(),
+                len: 64,
+                meta: meta(),
+            },
+            Symbol::Setting {
+                name: "GAIN".to_owned(),
+                ty: "Q15".into(),
+                range: -1.0..=0.99,
+                step_size: 0.01,
+                address: Address::Symbols,
+                meta: meta(),
+            },
+            Symbol::Setting {
+                name: "TIM1_CR1.CEN".to_owned(),
+                ty: "u32".into(),
+                range: 0.0..=1.0,
+                step_size: 1.0,
+                address: Address::Hardcoded {
+                    address: 0x40012C00,
+                    bits: Some(Bits { high: 0, low: 0 }),
+                },
+                meta: Metadata::default(),
+            },
+            Symbol::Action {
+                name: "RESET".to_owned(),
+            },
+            Symbol::Enum {
+                name: "Mode".to_owned(),
+                ty: PrimitiveType::i64,
+                variants: vec![
+                    ("Min".to_owned(), i64::MIN),
+                    ("Off".to_owned(), -1),
+                    ("On".to_owned(), 0),
+                    ("Max".to_owned(), i64::MAX),
+                ],
+            },
+            Symbol::Struct {
+                name: "Stats".to_owned(),
+                fields: vec![
+                    ("counter".to_owned(), "u32".into()),
+                    ("mode".to_owned(), "Mode".into()),
+                ],
+            },
+            Symbol::Registry {
+                name: "REGISTRY".to_owned(),
+                capacity: 16,
+            },
+            Symbol::MetricGroup {
+                name: "CURRENTS".to_owned(),
+                ty: "Currents".into(),
+                meta: meta(),
+            },
+        ]
+    }
+
+    fn decode_ok(data: &[u8]) -> Vec<Symbol> {
+        decode_all(data)
+            .into_iter()
+            .map(|s| s.expect("Failed to decode"))
+            .collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        for symbol in symbols() {
+            let decoded = decode_ok(&encode(&symbol));
+            assert_eq!(decoded.len(), 1);
+            assert!(
+                decoded[0] == symbol,
+                "{} changed in the round trip",
+                symbol.export_name()
+            );
+        }
+    }
+
+    #[test]
+    fn zeros_between_records() {
+        let mut data = vec![0, 0];
+        for symbol in symbols() {
+            data.extend(encode(&symbol));
+            data.extend([0; 3]);
+        }
+        assert!(decode_ok(&data) == symbols());
+    }
+
+    #[test]
+    fn truncated() {
+        let record = encode(&symbols()[1]);
+        for len in 1..record.len() {
+            let decoded = decode_all(&record[..len]);
+            assert_eq!(decoded.len(), 1);
+            assert!(matches!(decoded[0], Err(RecordError::Malformed)));
+        }
+    }
+
+    #[test]
+    fn oversized_length() {
+        // Record longer than the data
+        let decoded = decode_all(&[VERSION, 100, tag::NULL]);
+        assert!(matches!(decoded[..], [Err(RecordError::Malformed)]));
+
+        // Length which does not fit in a varint
+        let mut data = vec![VERSION];
+        data.extend([0xff; 10]);
+        let decoded = decode_all(&data);
+        assert!(matches!(decoded[..], [Err(RecordError::Malformed)]));
+
+        // String longer than the record
+        let decoded = decode_all(&[VERSION, 3, tag::STRING, 100, b'a']);
+        assert!(matches!(decoded[..], [Err(RecordError::Malformed)]));
+
+        // Value shorter than the record
+        let decoded = decode_all(&[VERSION, 2, tag::NULL, tag::NULL]);
+        assert!(matches!(decoded[..], [Err(RecordError::Malformed)]));
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let mut record = encode(&symbols()[0]);
+        record[0] = VERSION + 1;
+        let decoded = decode_all(&record);
+        assert!(matches!(
+            decoded[..],
+            [Err(RecordError::UnsupportedVersion(v))] if v == VERSION + 1
+        ));
+
+        // Records before it are still decoded
+        let mut data = encode(&symbols()[0]);
+        data.extend(record);
+        let decoded = decode_all(&data);
+        assert!(matches!(
+            decoded[..],
+            [Ok(_), Err(RecordError::UnsupportedVersion(_))]
+        ));
+    }
+}
@@ -1,17 +1,97 @@
 use std::ops::RangeInclusive;
 
-use crate::PrimitiveType;
+use crate::{PrimitiveType, ValueType};
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize, PartialEq)]
 pub enum Address {
     /// Use symbol's address
+    #[default]
     Symbols,
 
     /// Override address with a hardcoded address
-    Hardcoded { address: u64 },
+    Hardcoded {
+        address: u64,
+
+        /// Only use these bits of the value, such as a field of a peripheral register
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        bits: Option<Bits>,
+    },
+
+    /// Override address with the address of the static `name`, which the host reads from the
+    /// pointer stored in the static of the metric itself
+    Symbol { name: String },
 
     /// Override address with the value specified in metric `base_metric_name` with `offset`
     RelativeBaseMetricWithOffset { base_metric: String, offset: u64 },
+
+    /// Override address with the value specified in metric `base_metric` plus the first offset,
+    /// then for every further offset read the pointer at that address and add the offset
+    ///
+    /// The `len` offsets are stored as `u32`s in the contents of the symbol
+    PointerChain { base_metric: String, len: u64 },
+}
+
+/// Bits `low..=high` of a value, written as `[high:low]` in the macros
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct Bits {
+    pub high: u8,
+    pub low: u8,
+}
+
+impl Bits {
+    /// Largest value the bits can hold
+    pub fn max(&self) -> u64 {
+        u64::MAX >> (63 - (self.high - self.low))
+    }
+
+    /// The value of the bits within `word`
+    pub fn extract(&self, word: u64) -> u64 {
+        (word >> self.low) & self.max()
+    }
+
+    /// `word` with the bits replaced by `x`, which is truncated to fit
+    pub fn insert(&self, word: u64, x: u64) -> u64 {
+        let mask = self.max() << self.low;
+        (word & !mask) | ((x << self.low) & mask)
+    }
+}
+
+/// Optional information on how to show a metric or setting, all missing for firmware built before
+/// these existed
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct Metadata {
+    /// Such as `A`, shown after the value
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+
+    /// Shown when hovering a setting
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    /// Color of the plot, as `#rrggbb`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+
+    /// Metrics in the same group are shown together
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+}
+
+impl Metadata {
+    /// Red, green and blue of [Self::color], `None` if not set or not of the form `#rrggbb`
+    pub fn rgb(&self) -> Option<[u8; 3]> {
+        parse_color(self.color.as_deref()?)
+    }
+}
+
+/// Parse a color of the form `#rrggbb`
+pub fn parse_color(color: &str) -> Option<[u8; 3]> {
+    let hex = color.strip_prefix('#')?;
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i * 2..][..2], 16).ok();
+    Some([channel(0)?, channel(1)?, channel(2)?])
 }
 
 #[derive(serde::Serialize, serde::Deserialize, PartialEq)]
@@ -24,22 +104,90 @@ pub enum Symbol {
         expr: Option<String>,
 
         /// Type of value, i32, u8 etc.
-        ty: PrimitiveType,
+        ty: ValueType,
 
         /// Override address of symbol
         address: Address,
+
+        #[serde(flatten)]
+        meta: Metadata,
+    },
+    /// Array of values which are all published at once, see `probe_plotter::MetricArray`
+    MetricArray {
+        name: String,
+
+        /// Expression to apply to every element before plotting
+        expr: Option<String>,
+
+        /// Type of every element, i32, u8 etc.
+        ty: ValueType,
+
+        /// Number of elements
+        len: u64,
+
+        #[serde(flatten)]
+        meta: Metadata,
     },
     Setting {
         name: String,
 
         /// Type of value, i32, u8 etc.
-        ty: PrimitiveType,
+        ty: ValueType,
 
         /// Range of valid values
         range: RangeInclusive<f64>,
 
         /// Step size
         step_size: f64,
+
+        /// Override address of symbol, such as for a peripheral register. Missing for firmware
+        /// built before settings could have one.
+        #[serde(default)]
+        address: Address,
+
+        #[serde(flatten)]
+        meta: Metadata,
+    },
+    /// One-shot command triggered from the host, see `probe_plotter::Action`
+    Action { name: String },
+    /// Enum which can be used as the type of metrics and settings
+    Enum {
+        name: String,
+
+        /// The `#[repr]` of the enum
+        ty: PrimitiveType,
+
+        /// Name and discriminant of every variant
+        variants: Vec<(String, i64)>,
+    },
+    /// Struct which can be used as the type of metrics, every field is shown as a metric of its own
+    ///
+    /// The offset of every field is not known when the symbol is created, so the static of the
+    /// symbol contains the offsets as an array of `u32`, in the same order as `fields`.
+    Struct {
+        name: String,
+
+        /// Name and type of every field
+        fields: Vec<(String, ValueType)>,
+    },
+    /// Fixed capacity list of metrics which are added and removed at runtime, see
+    /// `probe_plotter::Registry`
+    Registry {
+        name: String,
+
+        /// Max number of metrics at the same time
+        capacity: u64,
+    },
+    /// Struct whose fields are always read together as they were at the same commit, see
+    /// `probe_plotter::MetricGroup`. Every field is shown as a metric of its own.
+    MetricGroup {
+        name: String,
+
+        /// The struct, which has a [Symbol::Struct] of its own
+        ty: ValueType,
+
+        #[serde(flatten)]
+        meta: Metadata,
     },
 }
 
@@ -47,22 +195,93 @@ impl Symbol {
     pub fn name(&self) -> &str {
         match self {
             Symbol::Metric { name, .. } => name,
+            Symbol::MetricArray { name, .. } => name,
             Symbol::Setting { name, .. } => name,
+            Symbol::Action { name } => name,
+            Symbol::Enum { name, .. } => name,
+            Symbol::Struct { name, .. } => name,
+            Symbol::Registry { name, .. } => name,
+            Symbol::MetricGroup { name, .. } => name,
         }
     }
     pub fn ty(&self) -> String {
         match self {
             Symbol::Metric { ty, .. } => ty.to_string(),
+            Symbol::MetricArray { ty, len, .. } => format!("[{ty}; {len}]"),
             Symbol::Setting { ty, .. } => ty.to_string(),
+            Symbol::Action { .. } => "Action".to_string(),
+            Symbol::Enum { ty, .. } => ty.to_string(),
+            Symbol::Struct { name, .. } => name.clone(),
+            Symbol::Registry { capacity, .. } => format!("Registry<{capacity}>"),
+            Symbol::MetricGroup { ty, .. } => format!("MetricGroup<{ty}>"),
         }
     }
+
+    /// Name of the static described by this symbol, which is looked up by the host after decoding
+    /// the record of the symbol. See [crate::record]
+    pub fn export_name(&self) -> String {
+        let kind = match self {
+            Symbol::Metric { .. } => "metric",
+            Symbol::MetricArray { .. } => "metric_array",
+            Symbol::Setting { .. } => "setting",
+            Symbol::Action { .. } => "action",
+            Symbol::Enum { .. } => "enum",
+            Symbol::Struct { .. } => "struct",
+            Symbol::Registry { .. } => "registry",
+            Symbol::MetricGroup { .. } => "metric_group",
+        };
+        format!("{}{kind}.{}", crate::record::EXPORT_PREFIX, self.name())
+    }
 }
 
 #[derive(Debug)]
 pub struct InvalidSymbolError(#[allow(dead_code)] serde_json::Error);
 
 impl Symbol {
+    /// Parse a symbol name written by older versions, which encoded the symbol as JSON
     pub fn demangle(raw: &str) -> Result<Self, InvalidSymbolError> {
         serde_json::from_str(raw).map_err(|e| InvalidSymbolError(e))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Bits;
+
+    #[test]
+    fn single_bit() {
+        let bits = Bits { high: 0, low: 0 };
+        assert_eq!(bits.max(), 1);
+        assert_eq!(bits.extract(0b1010), 0);
+        assert_eq!(bits.extract(0b1011), 1);
+        assert_eq!(bits.insert(0b1010, 1), 0b1011);
+        assert_eq!(bits.insert(0b1011, 0), 0b1010);
+    }
+
+    #[test]
+    fn field() {
+        let bits = Bits { high: 31, low: 28 };
+        assert_eq!(bits.max(), 0xf);
+        assert_eq!(bits.extract(0xa123_4567), 0xa);
+        assert_eq!(bits.insert(0xa123_4567, 0x5), 0x5123_4567);
+    }
+
+    #[test]
+    fn whole_word() {
+        let bits = Bits { high: 63, low: 0 };
+        assert_eq!(bits.max(), u64::MAX);
+        assert_eq!(bits.extract(u64::MAX - 1), u64::MAX - 1);
+        assert_eq!(bits.insert(0x1234, u64::MAX), u64::MAX);
+    }
+
+    #[test]
+    fn insert_truncates() {
+        let bits = Bits { high: 31, low: 28 };
+        assert_eq!(bits.insert(0x0123_4567, 0x1f), 0xf123_4567);
+
+        // Does not spill into the bits above
+        let bits = Bits { high: 3, low: 2 };
+        assert_eq!(bits.insert(0, 0b111), 0b1100);
+        assert_eq!(bits.insert(0xf0, 0b100), 0xf0);
+    }
+}
@@ -0,0 +1,53 @@
+use crate::{memory::Memory, setting::WriteStatus};
+
+/// One-shot command shown as a button, see `probe_plotter::Action`
+#[derive(Clone, Debug)]
+pub struct Action {
+    pub name: String,
+    pub address: u64,
+
+    /// Pending until the target has taken every press
+    pub status: WriteStatus,
+
+    /// Number of presses so far
+    presses: u32,
+}
+
+impl Action {
+    pub fn new(name: String, address: u64) -> Self {
+        Action {
+            name,
+            address,
+            status: WriteStatus::Applied,
+            presses: 0,
+        }
+    }
+
+    pub fn read(&mut self, core: &mut impl Memory) -> Result<(), probe_rs::Error> {
+        self.presses = core.read_word_32(self.address)?;
+        Ok(())
+    }
+
+    /// Press the button
+    ///
+    /// The storage is laid out as `probe_plotter::action::ActionStorage`, the number of presses
+    /// followed by the number of presses taken by the target.
+    pub fn trigger(&mut self, core: &mut impl Memory) -> Result<(), probe_rs::Error> {
+        self.presses = core.read_word_32(self.address)?.wrapping_add(1);
+        core.write_word_32(self.address, self.presses)?;
+        self.status = WriteStatus::Pending;
+        Ok(())
+    }
+
+    /// Check whether the target has taken every press, returns `true` if it just did
+    pub fn poll_status(&mut self, core: &mut impl Memory) -> Result<bool, probe_rs::Error> {
+        if self.status != WriteStatus::Pending
+            || core.read_word_32(self.address + 4)? != self.presses
+        {
+            return Ok(false);
+        }
+
+        self.status = WriteStatus::Applied;
+        Ok(true)
+    }
+}
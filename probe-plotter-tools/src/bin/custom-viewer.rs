@@ -1,11 +1,9 @@
 // A custom rerun viewer capable of showing and editing settings
 
-use probe_plotter_tools::{gui::MyApp, parse, probe_background_thread, setting::Setting};
-use rerun::external::{eframe, re_crash_handler, re_grpc_server, re_viewer, tokio};
-use std::{env, io::Read, sync::mpsc, thread, time::Duration};
+use probe_plotter_tools::{gui, parse, probe_background_thread};
+use std::{env, io::Read, time::Duration};
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+fn main() -> Result<(), Box<dyn std::error::Error>> {
     let help =
         "Usage: \nprobe-plotter /path/to/elf [chip] [update_rate_ms=10] [channel_mode=no change]";
 
@@ -40,79 +38,17 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .read_to_end(&mut elf_bytes)
         .unwrap();
 
-    let (metrics, settings, scan_region) = parse(&elf_bytes);
+    let parsed = parse(&elf_bytes).unwrap_or_else(|e| panic!("{e}"));
 
-    let main_thread_token = rerun::MainThreadToken::i_promise_i_am_on_the_main_thread();
-
-    // Direct calls using the `log` crate to stderr. Control with `RUST_LOG=debug` etc.
-
-    // Install handlers for panics and crashes that prints to stderr and send
-    // them to Rerun analytics (if the `analytics` feature is on in `Cargo.toml`).
-    re_crash_handler::install_crash_handlers(rerun::build_info());
-
-    // Listen for gRPC connections from Rerun's logging SDKs.
-    // There are other ways of "feeding" the viewer though - all you need is a `re_smart_channel::Receiver`.
-    let server_options = Default::default();
-    let rx = re_grpc_server::spawn_with_recv(
-        "0.0.0.0:9876".parse().unwrap(),
-        server_options,
-        re_grpc_server::shutdown::never(),
-    );
-
-    let (settings_update_sender, settings_update_receiver) = mpsc::channel::<Setting>();
-
-    let mut native_options = re_viewer::native::eframe_options(None);
-    native_options.viewport = native_options.viewport.with_app_id("probe-plotter");
-
-    let startup_options = re_viewer::StartupOptions::default();
-
-    // This is used for analytics, if the `analytics` feature is on in `Cargo.toml`
-    let app_env = re_viewer::AppEnvironment::Custom("probe-plotter-tools".to_owned());
-
-    let (initial_settings_sender, initial_settings_receiver) = mpsc::channel();
-
-    // probe-thread
-    thread::spawn(move || {
+    gui::run_viewer(move |settings_update_receiver, settings_sender| {
         probe_background_thread(
             update_rate,
             channel_mode,
             &target,
             &elf_bytes,
-            settings,
-            metrics,
-            scan_region,
+            parsed,
             settings_update_receiver,
-            initial_settings_sender,
+            settings_sender,
         )
-    });
-
-    // Receive initial settings from to probe-thread thread
-    let settings = initial_settings_receiver.recv().unwrap();
-
-    let window_title = "probe-plotter";
-    eframe::run_native(
-        window_title,
-        native_options,
-        Box::new(move |cc| {
-            re_viewer::customize_eframe_and_setup_renderer(cc)?;
-
-            let mut rerun_app = re_viewer::App::new(
-                main_thread_token,
-                re_viewer::build_info(),
-                app_env,
-                startup_options,
-                cc,
-                None,
-                re_viewer::AsyncRuntimeHandle::from_current_tokio_runtime_or_wasmbindgen()?,
-            );
-            rerun_app.add_log_receiver(rx);
-            Ok(Box::new(MyApp::new(
-                rerun_app,
-                settings,
-                settings_update_sender,
-            )))
-        }),
-    )?;
-
-    Ok(())
+    })
 }
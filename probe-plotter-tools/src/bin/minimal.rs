@@ -1,6 +1,6 @@
 // Connect to the regular rerun viewer. This does not support Settings, only Metrics
 
-use probe_plotter_tools::{metric::Status, parse_elf_file};
+use probe_plotter_tools::{Parsed, metric::Status, parse_elf_file};
 use shunting::MathContext;
 use std::time::Duration;
 
@@ -16,10 +16,30 @@ fn main() {
     let mut session = probe_rs::Session::auto_attach(target, Default::default()).unwrap();
     let mut core = session.core(0).unwrap();
 
-    let (mut metrics, _settings, _) = parse_elf_file(&elf_path);
+    let Parsed {
+        mut metrics,
+        mut metric_arrays,
+        mut registries,
+        mut metric_groups,
+        stream,
+        ..
+    } = parse_elf_file(&elf_path).unwrap_or_else(|e| panic!("{e}"));
+    let mut stream = stream.map(|stream| {
+        let channel = stream.attach(&mut core).unwrap();
+        (stream, channel)
+    });
     for m in &metrics {
         println!("{}: {:?}", m.name, m.address);
     }
+    for a in &metric_arrays {
+        println!("{}: {:?}", a.name, a.address);
+    }
+    for r in &registries {
+        println!("{}: {:?}", r.name, r.address);
+    }
+    for g in &metric_groups {
+        println!("{}: {:?}", g.name, g.address);
+    }
 
     println!();
     println!("---------------------Running---------------------------");
@@ -29,17 +49,52 @@ fn main() {
         .spawn()
         .unwrap();
 
+    let group_metrics = metric_groups
+        .iter()
+        .flat_map(|g| g.fields.iter().map(|(_, m)| m));
+    for m in metrics.iter().chain(group_metrics) {
+        m.log_style(&rec);
+    }
+
     let mut math_ctx = MathContext::new();
     loop {
-        for m in &mut metrics {
+        if let Some((stream, channel)) = &mut stream {
+            stream
+                .receive(&mut core, channel, &rec, &mut metrics, &mut math_ctx)
+                .unwrap();
+        }
+
+        for g in &mut metric_groups {
+            g.update(&mut core, &rec, &mut math_ctx).unwrap();
+        }
+
+        for r in &mut registries {
+            for name in r.rescan(&mut core).unwrap() {
+                rec.log(name, &rerun::Clear::flat()).unwrap();
+            }
+        }
+
+        let dynamic_metrics = registries.iter_mut().flat_map(|r| &mut r.metrics);
+        for m in metrics.iter_mut().chain(dynamic_metrics) {
+            if stream.as_ref().is_some_and(|(s, _)| s.is_streamed(m)) {
+                continue;
+            }
             m.read(&mut core, &mut math_ctx).unwrap();
+            let previous = m.last_value;
             if let Some((x, s)) = m.compute(&mut math_ctx) {
                 if let Status::New = s {
-                    rec.log(m.name.clone(), &rerun::Scalars::single(x)).unwrap();
+                    m.log(&rec, x, previous);
                 } else {
                     std::thread::sleep(Duration::from_millis(1));
                 }
             }
         }
+
+        for a in &mut metric_arrays {
+            a.read(&mut core).unwrap();
+            if let Some((values, Status::New)) = a.compute(&mut math_ctx) {
+                a.log(&rec, &values);
+            }
+        }
     }
 }
@@ -2,9 +2,16 @@
 
 use std::sync::mpsc;
 
-use rerun::external::{eframe, egui, re_memory, re_viewer};
+use rerun::external::{
+    eframe, egui, re_crash_handler, re_grpc_server, re_memory, re_viewer, tokio,
+};
 
-use crate::setting::Setting;
+use crate::{
+    Type,
+    action::Action,
+    metric::variant_name,
+    setting::{Control, Setting, WriteStatus},
+};
 
 // By using `re_memory::AccountingAllocator` Rerun can keep track of exactly how much memory it is using,
 // and prune the data store when it goes above a certain limit.
@@ -13,24 +20,104 @@ use crate::setting::Setting;
 static GLOBAL: re_memory::AccountingAllocator<mimalloc::MiMalloc> =
     re_memory::AccountingAllocator::new(mimalloc::MiMalloc);
 
+/// Run the custom viewer with a settings panel, blocks until the viewer is closed
+///
+/// Must be called from the main thread. `background_thread` is started in a thread of its own, it
+/// should send the initial settings and then apply the settings it receives, sending them back
+/// once handled by the target, see [crate::probe_background_thread].
+pub fn run_viewer(
+    background_thread: impl FnOnce(mpsc::Receiver<Control>, mpsc::Sender<Vec<Control>>) + Send + 'static,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let runtime = tokio::runtime::Runtime::new()?;
+    let _guard = runtime.enter();
+
+    let main_thread_token = rerun::MainThreadToken::i_promise_i_am_on_the_main_thread();
+
+    // Direct calls using the `log` crate to stderr. Control with `RUST_LOG=debug` etc.
+
+    // Install handlers for panics and crashes that prints to stderr and send
+    // them to Rerun analytics (if the `analytics` feature is on in `Cargo.toml`).
+    re_crash_handler::install_crash_handlers(rerun::build_info());
+
+    // Listen for gRPC connections from Rerun's logging SDKs.
+    // There are other ways of "feeding" the viewer though - all you need is a `re_smart_channel::Receiver`.
+    let server_options = Default::default();
+    let rx = re_grpc_server::spawn_with_recv(
+        "0.0.0.0:9876".parse().unwrap(),
+        server_options,
+        re_grpc_server::shutdown::never(),
+    );
+
+    let (settings_update_sender, settings_update_receiver) = mpsc::channel::<Control>();
+
+    let mut native_options = re_viewer::native::eframe_options(None);
+    native_options.viewport = native_options.viewport.with_app_id("probe-plotter");
+
+    let startup_options = re_viewer::StartupOptions::default();
+
+    // This is used for analytics, if the `analytics` feature is on in `Cargo.toml`
+    let app_env = re_viewer::AppEnvironment::Custom("probe-plotter-tools".to_owned());
+
+    let (settings_sender, settings_receiver) = mpsc::channel();
+
+    // probe-thread
+    std::thread::spawn(move || background_thread(settings_update_receiver, settings_sender));
+
+    // Receive initial settings from to probe-thread thread
+    let settings = settings_receiver.recv().unwrap();
+
+    let window_title = "probe-plotter";
+    eframe::run_native(
+        window_title,
+        native_options,
+        Box::new(move |cc| {
+            re_viewer::customize_eframe_and_setup_renderer(cc)?;
+
+            let mut rerun_app = re_viewer::App::new(
+                main_thread_token,
+                re_viewer::build_info(),
+                app_env,
+                startup_options,
+                cc,
+                None,
+                re_viewer::AsyncRuntimeHandle::from_current_tokio_runtime_or_wasmbindgen()?,
+            );
+            rerun_app.add_log_receiver(rx);
+            Ok(Box::new(MyApp::new(
+                rerun_app,
+                settings,
+                settings_update_sender,
+                settings_receiver,
+            )))
+        }),
+    )?;
+
+    Ok(())
+}
+
 pub struct MyApp {
     rerun_app: re_viewer::App,
-    settings: Vec<Setting>,
+    settings: Vec<Control>,
 
-    /// Send settigns here to apply them
-    settings_channel: mpsc::Sender<Setting>,
+    /// Send settigns here to apply them, or actions to press them
+    settings_channel: mpsc::Sender<Control>,
+
+    /// Settings and actions which the target has handled
+    handled_settings: mpsc::Receiver<Vec<Control>>,
 }
 
 impl MyApp {
     pub fn new(
         rerun_app: re_viewer::App,
-        settings: Vec<Setting>,
-        settings_channel: mpsc::Sender<Setting>,
+        settings: Vec<Control>,
+        settings_channel: mpsc::Sender<Control>,
+        handled_settings: mpsc::Receiver<Vec<Control>>,
     ) -> Self {
         Self {
             rerun_app,
             settings,
             settings_channel,
+            handled_settings,
         }
     }
 }
@@ -63,17 +150,92 @@ impl MyApp {
         });
         ui.separator();
 
-        for setting in &mut self.settings {
-            if ui
-                .add(
-                    egui::Slider::new(&mut setting.value, setting.range.clone())
-                        .step_by(setting.step_size)
-                        .text(&setting.name),
-                )
-                .changed()
+        for handled in self.handled_settings.try_iter().flatten() {
+            if let Some(control) = self
+                .settings
+                .iter_mut()
+                .find(|c| c.name() == handled.name())
             {
-                self.settings_channel.send(setting.clone()).unwrap();
+                *control = handled;
             }
         }
+
+        for control in &mut self.settings {
+            ui.horizontal(|ui| match control {
+                Control::Setting(setting) => Self::setting_ui(ui, setting, &self.settings_channel),
+                Control::Action(action) => Self::action_ui(ui, action, &self.settings_channel),
+            });
+        }
+    }
+
+    fn action_ui(ui: &mut egui::Ui, action: &mut Action, settings_channel: &mpsc::Sender<Control>) {
+        if ui.button(&action.name).clicked() {
+            action.status = WriteStatus::Pending;
+            settings_channel
+                .send(Control::Action(action.clone()))
+                .unwrap();
+        }
+
+        if action.status == WriteStatus::Pending {
+            ui.weak("pending");
+        }
+    }
+
+    fn setting_ui(
+        ui: &mut egui::Ui,
+        setting: &mut Setting,
+        settings_channel: &mpsc::Sender<Control>,
+    ) {
+        let (response, changed) = if let Some(variants) = &setting.variants {
+            let before = setting.value;
+            let response = egui::ComboBox::from_label(&setting.name)
+                .selected_text(variant_name(variants, setting.value).unwrap_or("<invalid>"))
+                .show_ui(ui, |ui| {
+                    for (name, discriminant) in variants {
+                        ui.selectable_value(&mut setting.value, *discriminant as f64, name);
+                    }
+                })
+                .response;
+            (response, setting.value != before)
+        } else if setting.ty == Type::bool {
+            let mut checked = setting.value != 0.0;
+            let response = ui.checkbox(&mut checked, &setting.name);
+            setting.value = checked as u8 as f64;
+            let changed = response.changed();
+            (response, changed)
+        } else {
+            // Ranges such as the full range of an i32, which is the default, are too large to pick a
+            // value from with a linear slider
+            let span = setting.range.end() - setting.range.start();
+            let suffix = match &setting.meta.unit {
+                Some(unit) => format!(" {unit}"),
+                None => String::new(),
+            };
+            let response = ui.add(
+                egui::Slider::new(&mut setting.value, setting.range.clone())
+                    .step_by(setting.step_size)
+                    .logarithmic(span > 1e6)
+                    .suffix(suffix)
+                    .text(&setting.name),
+            );
+            let changed = response.changed();
+            (response, changed)
+        };
+        if let Some(description) = &setting.meta.description {
+            response.on_hover_text(description);
+        }
+
+        if changed {
+            setting.status = WriteStatus::Pending;
+            settings_channel
+                .send(Control::Setting(setting.clone()))
+                .unwrap();
+        }
+
+        match setting.status {
+            WriteStatus::Pending => ui.weak("pending"),
+            WriteStatus::Applied => ui.weak("applied"),
+            WriteStatus::Rejected => ui.colored_label(ui.visuals().error_fg_color, "rejected"),
+        };
     }
 }
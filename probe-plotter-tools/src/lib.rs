@@ -1,36 +1,181 @@
+pub mod action;
 pub mod gui;
+#[cfg(target_os = "linux")]
+pub mod local;
+pub mod memory;
 pub mod metric;
+pub mod metric_array;
+pub mod metric_group;
+pub mod registry;
 pub mod setting;
+pub mod stream;
 
-use std::{io::Read, sync::mpsc, time::Duration};
+use std::{collections::HashMap, io::Read, sync::mpsc, time::Duration};
 
 use defmt_decoder::DecodeError;
 use defmt_parser::Level;
-use object::{Object, ObjectSymbol};
+use object::{Object, ObjectSection, ObjectSymbol, RelocationTarget};
 use probe_plotter_common::{
-    PrimitiveType,
-    symbol::{self, Symbol},
+    PrimitiveType, ValueType, parse_expr, record,
+    symbol::{self, Bits, Metadata, Symbol},
 };
 use probe_rs::{
-    Core, MemoryInterface,
+    Core,
     rtt::{self, ChannelMode, Rtt},
 };
 use rerun::TextLogLevel;
 use shunting::{MathContext, RPNExpr, ShuntingParser};
 
-use crate::{metric::Metric, setting::Setting};
+use crate::{
+    action::Action,
+    memory::Memory,
+    metric::{Metric, Status},
+    metric_array::MetricArray,
+    metric_group::MetricGroup,
+    registry::Registry,
+    setting::{Control, Setting},
+    stream::{Channel, Stream},
+};
 
 #[derive(Debug)]
 pub enum Address {
     Fixed(u64),
-    /// `base_expression` expression to calculate the address
+    /// Only `bits` of the word at the address, see [read_bits]
+    Bitfield {
+        address: u64,
+        bits: Bits,
+    },
+    /// Storage created by `make_metric!` and friends, see [read_storage]
+    Storage(u64, Layout),
+    /// `base_expression` expression to calculate the address, see [metric::follow_pointers] for
+    /// how the offsets are applied
     BaseWithOffset {
         base_expression: RPNExpr,
-        offset: u64,
+        offsets: Vec<u64>,
+        layout: Layout,
     },
 }
 
-pub fn read_value(core: &mut Core, address: u64, ty: Type) -> Result<f64, probe_rs::Error> {
+/// The firmware was built with a version of probe-plotter which writes the metadata in a format
+/// this version of probe-plotter-tools does not understand
+#[derive(Debug)]
+pub struct IncompatibleVersion {
+    /// Format version of the firmware
+    pub firmware: u64,
+    /// Format version understood by this version of probe-plotter-tools
+    pub supported: u64,
+}
+
+impl std::fmt::Display for IncompatibleVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let age = if self.firmware > self.supported {
+            "newer"
+        } else {
+            "older"
+        };
+        write!(
+            f,
+            "The firmware was built with a {age} version of probe-plotter using metadata format {}, this version of probe-plotter-tools only supports format {}. Use the same version of probe-plotter and probe-plotter-tools",
+            self.firmware, self.supported
+        )
+    }
+}
+
+impl std::error::Error for IncompatibleVersion {}
+
+/// Number of attempts to get a consistent read of a 64 bit value before giving up
+const WIDE_READ_ATTEMPTS: usize = 8;
+
+/// How `make_metric!` and friends lay out values, which depends on the pointer width of the target
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// 64 bit values are stored as `probe_plotter::metric::Wide`, pointers are 4 bytes
+    Bits32,
+    /// 64 bit values are stored as is, pointers are 8 bytes
+    Bits64,
+}
+
+impl Layout {
+    /// Layout of the target the ELF file was built for
+    pub fn of(elf: &object::File) -> Self {
+        if elf.is_64() {
+            Layout::Bits64
+        } else {
+            Layout::Bits32
+        }
+    }
+
+    /// Whether values of this type are stored as `probe_plotter::metric::Wide`
+    pub fn is_stored_wide(self, ty: Type) -> bool {
+        ty.is_wide() && self == Layout::Bits32
+    }
+
+    /// Number of bytes used to store a value of this type
+    pub fn storage_size(self, ty: Type) -> u64 {
+        if ty.is_wide() && !self.is_stored_wide(ty) {
+            8
+        } else {
+            ty.storage_size()
+        }
+    }
+
+    pub fn pointer_size(self) -> u64 {
+        match self {
+            Layout::Bits32 => 4,
+            Layout::Bits64 => 8,
+        }
+    }
+
+    /// Type to read `usize` values as, such as the pointers of `make_ptr!`
+    pub fn pointer_type(self) -> Type {
+        match self {
+            Layout::Bits32 => Type::u32,
+            Layout::Bits64 => Type::u64,
+        }
+    }
+}
+
+/// Read the `ty` sized word at `address` as is, `ty` is always an unsigned integer
+fn read_word(core: &mut impl Memory, address: u64, ty: Type) -> Result<u64, probe_rs::Error> {
+    Ok(match ty {
+        Type::u8 => core.read_word_8(address)? as u64,
+        Type::u16 => core.read_word_16(address)? as u64,
+        Type::u32 => core.read_word_32(address)? as u64,
+        _ => core.read_word_64(address)?,
+    })
+}
+
+/// Read the bits of the `ty` sized word at `address`, such as a field of a peripheral register
+pub fn read_bits(
+    core: &mut impl Memory,
+    address: u64,
+    ty: Type,
+    bits: Bits,
+) -> Result<u64, probe_rs::Error> {
+    Ok(bits.extract(read_word(core, address, ty)?))
+}
+
+/// Replace the bits of the `ty` sized word at `address` by `x`, leaving the other bits as they are
+pub fn write_bits(
+    core: &mut impl Memory,
+    address: u64,
+    ty: Type,
+    bits: Bits,
+    x: u64,
+) -> Result<(), probe_rs::Error> {
+    let word = read_word(core, address, ty)?;
+    write_value(core, address, ty, bits.insert(word, x))
+}
+
+/// Read a value from an arbitrary address
+///
+/// 64 bit values are read twice until two reads in a row agree, to avoid showing a value which was
+/// half updated between the reads. Returns `None` if no consistent value could be read.
+pub fn read_value(
+    core: &mut impl Memory,
+    address: u64,
+    ty: Type,
+) -> Result<Option<f64>, probe_rs::Error> {
     let x = match ty {
         Type::u8 => core.read_word_8(address)? as f64,
         Type::u16 => core.read_word_16(address)? as f64,
@@ -41,12 +186,232 @@ pub fn read_value(core: &mut Core, address: u64, ty: Type) -> Result<f64, probe_
         Type::i32 => core.read_word_32(address)? as i32 as f64,
 
         Type::f32 => f32::from_bits(core.read_word_32(address)?) as f64,
+
+        Type::bool => (core.read_word_8(address)? != 0) as u8 as f64,
+
+        Type::Q { frac, .. } => {
+            let storage = ty.fixed_point_storage().unwrap();
+            let raw = read_value(core, address, storage)?;
+            return Ok(raw.map(|raw| raw / 2f64.powi(frac.into())));
+        }
+
+        Type::u64 | Type::i64 | Type::f64 => {
+            let mut last = core.read_word_64(address)?;
+            for _ in 0..WIDE_READ_ATTEMPTS {
+                let bits = core.read_word_64(address)?;
+                if bits == last {
+                    return Ok(Some(wide_from_bits(bits, ty)));
+                }
+                last = bits;
+            }
+            return Ok(None);
+        }
     };
 
-    Ok(x)
+    Ok(Some(x))
+}
+
+/// Read a value from the storage of a metric or setting created by `make_metric!` or `make_setting!`
+///
+/// 64 bit values are stored as `probe_plotter::metric::Wide` which contains a pair of sequence
+/// counters. The value is consistent if the counters are equal. Returns `None` if no consistent
+/// value could be read.
+pub fn read_storage(
+    core: &mut impl Memory,
+    address: u64,
+    ty: Type,
+    layout: Layout,
+) -> Result<Option<f64>, probe_rs::Error> {
+    if !layout.is_stored_wide(ty) {
+        return read_value(core, address, ty);
+    }
+
+    for _ in 0..WIDE_READ_ATTEMPTS {
+        // Read everything in one go to keep the window for the target to write during the read small
+        let mut words = [0; 4];
+        core.read_32(address, &mut words)?;
+        let [end, lo, hi, start] = words;
+        if start == end {
+            return Ok(Some(wide_from_bits(lo as u64 | (hi as u64) << 32, ty)));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Write a value to the storage of a setting created by `make_setting!`
+///
+/// 64 bit values are written according to the protocol described in `probe_plotter::metric::Wide`
+pub fn write_storage(
+    core: &mut impl Memory,
+    address: u64,
+    ty: Type,
+    bits: u64,
+    layout: Layout,
+) -> Result<(), probe_rs::Error> {
+    if !layout.is_stored_wide(ty) {
+        return write_value(core, address, ty, bits);
+    }
+
+    let seq = core.read_word_32(address + 12)?.wrapping_add(1);
+    core.write_word_32(address + 12, seq)?;
+    core.write_32(address + 4, &[bits as u32, (bits >> 32) as u32])?;
+    core.write_word_32(address, seq)
+}
+
+/// Write a value to an arbitrary address, see [read_value]
+///
+/// 64 bit values are written as is, never as `probe_plotter::metric::Wide` unlike values written
+/// by [write_storage]
+pub fn write_value(
+    core: &mut impl Memory,
+    address: u64,
+    ty: Type,
+    bits: u64,
+) -> Result<(), probe_rs::Error> {
+    match ty {
+        Type::u8 | Type::i8 | Type::bool => core.write_word_8(address, bits as u8),
+        Type::u16 | Type::i16 => core.write_word_16(address, bits as u16),
+        Type::u32 | Type::i32 | Type::f32 => core.write_word_32(address, bits as u32),
+        Type::Q { .. } => write_value(core, address, ty.fixed_point_storage().unwrap(), bits),
+        Type::u64 | Type::i64 | Type::f64 => core.write_word_64(address, bits),
+    }
+}
+
+/// Decode a value from its little endian representation as stored on the target
+///
+/// 64 bit values stored as `probe_plotter::metric::Wide` are assumed to already be known to be
+/// consistent
+pub fn value_from_bytes(bytes: &[u8], ty: Type, layout: Layout) -> f64 {
+    let word = |i: usize| u32::from_le_bytes(bytes[i * 4..][..4].try_into().unwrap());
+    match ty {
+        Type::u8 => bytes[0] as f64,
+        Type::u16 => u16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+        Type::u32 => word(0) as f64,
+
+        Type::i8 => bytes[0] as i8 as f64,
+        Type::i16 => i16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+        Type::i32 => word(0) as i32 as f64,
+
+        Type::f32 => f32::from_bits(word(0)) as f64,
+
+        Type::bool => (bytes[0] != 0) as u8 as f64,
+
+        Type::Q { frac, .. } => {
+            let storage = ty.fixed_point_storage().unwrap();
+            value_from_bytes(bytes, storage, layout) / 2f64.powi(frac.into())
+        }
+
+        Type::u64 | Type::i64 | Type::f64 if !layout.is_stored_wide(ty) => {
+            wide_from_bits(word(0) as u64 | (word(1) as u64) << 32, ty)
+        }
+        Type::u64 | Type::i64 | Type::f64 => {
+            wide_from_bits(word(1) as u64 | (word(2) as u64) << 32, ty)
+        }
+    }
+}
+
+/// Number of bytes of a value stored as is rather than as `probe_plotter::metric::Wide`, such as
+/// a field of a struct or a sample sent with `Metric::push`, regardless of the target
+pub fn raw_size(ty: Type) -> u64 {
+    Layout::Bits64.storage_size(ty)
+}
+
+/// Like [value_from_bytes] but for values stored as is, see [raw_size]
+pub fn raw_value_from_bytes(bytes: &[u8], ty: Type) -> f64 {
+    value_from_bytes(bytes, ty, Layout::Bits64)
+}
+
+fn wide_from_bits(bits: u64, ty: Type) -> f64 {
+    match ty {
+        Type::u64 => bits as f64,
+        Type::i64 => bits as i64 as f64,
+        Type::f64 => f64::from_bits(bits),
+        _ => unreachable!("{ty} is not a 64 bit type"),
+    }
 }
 
 pub type Type = PrimitiveType;
+
+/// Name and discriminant of every variant of an enum
+pub type Variants = Vec<(String, i64)>;
+
+/// Name, type and offset of every field of a struct
+type StructFields = Vec<(String, ValueType, u64)>;
+
+/// Read the `u32`s stored in the contents of a symbol, such as the field offsets of a
+/// [Symbol::Struct]
+fn read_offsets<'data>(
+    elf: &object::File<'data>,
+    entry: &impl ObjectSymbol<'data>,
+    count: usize,
+) -> Option<Vec<u64>> {
+    let section = elf.section_by_index(entry.section_index()?).ok()?;
+    let data = section.data().ok()?;
+    let start = usize::try_from(entry.address().checked_sub(section.address())?).ok()?;
+    let bytes = data.get(start..start + count * 4)?;
+    Some(
+        bytes
+            .chunks_exact(4)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()) as u64)
+            .collect(),
+    )
+}
+
+/// Read the pointer stored in the contents of a symbol, such as the address of the static plotted by
+/// `make_metric_from_symbol!`
+///
+/// Position independent executables, such as the ones read by [local], have the pointer in a
+/// dynamic relocation instead of in the contents.
+fn read_pointer<'data>(
+    elf: &object::File<'data>,
+    entry: &impl ObjectSymbol<'data>,
+    layout: Layout,
+) -> Option<u64> {
+    let relocation = elf
+        .dynamic_relocations()
+        .into_iter()
+        .flatten()
+        .find(|(offset, _)| *offset == entry.address());
+    if let Some((_offset, relocation)) = relocation
+        && relocation.target() == RelocationTarget::Absolute
+        && !relocation.has_implicit_addend()
+    {
+        return Some(relocation.addend() as u64);
+    }
+
+    let section = elf.section_by_index(entry.section_index()?).ok()?;
+    let data = section.data().ok()?;
+    let start = usize::try_from(entry.address().checked_sub(section.address())?).ok()?;
+    let bytes = data.get(start..start + layout.pointer_size() as usize)?;
+    Some(match *bytes {
+        [a, b, c, d] => u32::from_le_bytes([a, b, c, d]) as u64,
+        _ => u64::from_le_bytes(bytes.try_into().ok()?),
+    })
+}
+
+/// Collect the fields of a struct with their full names, replacing fields which are structs
+/// themselves by their fields
+fn flatten_struct(
+    name: &str,
+    fields: &StructFields,
+    offset: u64,
+    structs: &HashMap<String, StructFields>,
+    out: &mut StructFields,
+) {
+    for (field_name, ty, field_offset) in fields {
+        let name = format!("{name}.{field_name}");
+        let offset = offset + field_offset;
+        let inner_fields = match ty {
+            ValueType::Named(type_name) => structs.get(type_name),
+            ValueType::Primitive(_) => None,
+        };
+        match inner_fields {
+            Some(inner_fields) => flatten_struct(&name, inner_fields, offset, structs, out),
+            None => out.push((name, ty.clone(), offset)),
+        }
+    }
+}
 /*
 // From https://github.com/gimli-rs/gimli/blob/master/crates/examples/src/bin/simple.rs
 mod from_gimli_example {
@@ -121,22 +486,108 @@ gimli::Reader
     }
 }*/
 
+/// Everything found in an elf file by [parse]
+pub struct Parsed {
+    pub metrics: Vec<Metric>,
+    pub metric_arrays: Vec<MetricArray>,
+    pub settings: Vec<Setting>,
+    pub actions: Vec<Action>,
+    pub registries: Vec<Registry>,
+    pub metric_groups: Vec<MetricGroup>,
+
+    /// Region to look for the RTT control block in
+    pub scan_region: rtt::ScanRegion,
+
+    /// Set if the firmware sends samples with `Metric::push`
+    pub stream: Option<Stream>,
+}
+
+pub fn parse(elf_bytes: &[u8]) -> Result<Parsed, IncompatibleVersion> {
+    parse_with_bias(elf_bytes, 0)
+}
+
+/// Like [parse] but with `bias` added to the address of every symbol, for executables which are
+/// loaded at a different address than the one in the file, see [local]
 // Most of this is taken from https://github.com/knurling-rs/defmt/blob/8e517f8d7224237893e39337a61de8ef98b341f2/decoder/src/elf2table/mod.rs and modified
-pub fn parse(elf_bytes: &[u8]) -> (Vec<Metric>, Vec<Setting>, rtt::ScanRegion) {
+pub fn parse_with_bias(elf_bytes: &[u8], bias: u64) -> Result<Parsed, IncompatibleVersion> {
     let elf = object::File::parse(elf_bytes).unwrap();
+    let layout = Layout::of(&elf);
 
+    let mut symbols = Vec::new();
+    let mut structs = HashMap::new();
+    // Offsets of `make_metric_from_base_with_offset!` metrics by name
+    let mut pointer_chains = HashMap::new();
     let mut metrics = Vec::new();
+    let mut metric_arrays = Vec::new();
     let mut settings = Vec::new();
+    let mut actions = Vec::new();
+    let mut registries = Vec::new();
+    let mut metric_groups = Vec::new();
 
     let mut scan_region = rtt::ScanRegion::Ram;
 
+    // Firmware built with this version has the symbols in a section of their own, older versions
+    // encoded them as JSON in the names of the statics
+    let records = elf
+        .section_by_name(record::SECTION)
+        .and_then(|section| section.data().ok());
+
+    // Value of a `u32` static
+    let find_u32 = |name| {
+        let entry = elf.symbols().find(|entry| entry.name() == Ok(name))?;
+        Some(read_offsets(&elf, &entry, 1)?[0])
+    };
+
+    // Firmware built before the version was added has no version symbol, its JSON symbol names
+    // are still understood
+    let version = find_u32(record::VERSION_SYMBOL);
+    if let Some(version) = version
+        && version != u64::from(record::VERSION)
+    {
+        return Err(IncompatibleVersion {
+            firmware: version,
+            supported: record::VERSION.into(),
+        });
+    }
+    if version.is_some() && records.is_none() {
+        println!(
+            "Found no {} section, make sure the linker keeps it",
+            record::SECTION
+        );
+    }
+
+    // Ticks per second of the timestamps of pushed samples and of commits of metric groups
+    let frequency = find_u32(stream::FREQUENCY_SYMBOL).filter(|&frequency| frequency > 0);
+    let no_frequency = || {
+        println!("Found no timestamp frequency, make sure `probe_plotter::timestamp!` is used");
+    };
+
+    // Samples sent by `Metric::push`, the RTT control block is only there when that is used
+    let find_address = |name| {
+        let entry = elf.symbols().find(|entry| entry.name() == Ok(name))?;
+        Some(entry.address().wrapping_add(bias))
+    };
+    let stream = find_address(stream::SYMBOL)
+        .zip(find_address(stream::DROPPED_SYMBOL))
+        .and_then(|(address, dropped_address)| match frequency {
+            Some(frequency) => Some(Stream::new(address, dropped_address, layout, frequency)),
+            None => {
+                no_frequency();
+                None
+            }
+        });
+
+    // Statics described by the records by export name, see `Symbol::export_name`
+    let mut exported = HashMap::new();
+
     for entry in elf.symbols() {
         let Ok(name) = entry.name() else {
             eprintln!("Failed to get name of symbol: {entry:?}");
             continue;
         };
 
-        let name = rustc_demangle::demangle(name).to_string();
+        let demangled = rustc_demangle::demangle(name);
+        let name = demangled.to_string();
 
         eprintln!("  {name}");
 
@@ -147,6 +598,13 @@ pub fn parse(elf_bytes: &[u8]) -> (Vec<Metric>, Vec<Setting>, rtt::ScanRegion) {
             continue;
         }
 
+        if records.is_some() {
+            if name.starts_with(record::EXPORT_PREFIX) {
+                exported.insert(name, entry.index());
+            }
+            continue;
+        }
+
         let sym = match Symbol::demangle(&name) {
             Ok(sym) => sym,
             Err(e) => {
@@ -156,20 +614,140 @@ pub fn parse(elf_bytes: &[u8]) -> (Vec<Metric>, Vec<Setting>, rtt::ScanRegion) {
                 continue;
             }
         };
+        symbols.push((entry.index(), sym));
+    }
 
-        let do_math = |name: &str, math_ctx_variable_name: &str, expr_str| match expr_str {
-            Some(expr_str) => {
-                let expr = ShuntingParser::parse_str(expr_str).unwrap();
-                let math_ctx = MathContext::new();
-                math_ctx.setvar(math_ctx_variable_name, shunting::MathOp::Number(0.0));
-                math_ctx
-                    .eval(&expr)
-                    .unwrap_or_else(|_| panic!("For metric: {name}, failed to evaluate {expr:?}, Use the metrics name as name for the value in the expression"));
-                Some(expr)
+    for sym in records.map(record::decode_all).unwrap_or_default() {
+        let sym = match sym {
+            Ok(sym) => sym,
+            Err(record::RecordError::UnsupportedVersion(version)) => {
+                println!(
+                    "Metadata version {version} is not supported, the firmware was built with a newer version of probe-plotter"
+                );
+                continue;
+            }
+            Err(e) => {
+                println!("Failed to decode metadata: {e:?}");
+                continue;
+            }
+        };
+
+        // The linker removes the statics of metrics which are never created, but keeps their
+        // records
+        if let Some(&index) = exported.get(&sym.export_name()) {
+            symbols.push((index, sym));
+        }
+    }
+
+    // Field offsets of structs and the offsets of pointer chains are stored in their statics
+    symbols.retain(|(index, sym)| {
+        let Ok(entry) = elf.symbol_by_index(*index) else {
+            return false;
+        };
+
+        if let Symbol::Struct { name, fields } = sym {
+            let Some(offsets) = read_offsets(&elf, &entry, fields.len()) else {
+                println!("Failed to read field offsets of struct {name}");
+                return false;
+            };
+            let fields: StructFields = fields
+                .iter()
+                .zip(offsets)
+                .map(|((field_name, ty), offset)| (field_name.clone(), ty.clone(), offset))
+                .collect();
+            structs.insert(name.clone(), fields);
+        }
+
+        if let Symbol::Metric {
+            name,
+            address: symbol::Address::PointerChain { len, .. },
+            ..
+        } = sym
+        {
+            let Some(offsets) = read_offsets(&elf, &entry, *len as usize) else {
+                println!("Failed to read offsets of {name}");
+                return false;
+            };
+            pointer_chains.insert(name.clone(), offsets);
+        }
+
+        true
+    });
+
+    // Enums are referred to by name from metrics and settings
+    let enums: HashMap<String, (Type, Variants)> = symbols
+        .iter()
+        .filter_map(|(_index, sym)| match sym {
+            Symbol::Enum { name, ty, variants } => Some((name.clone(), (*ty, variants.clone()))),
+            _ => None,
+        })
+        .collect();
+
+    let resolve_type = |name: &str, ty: ValueType| match ty {
+        ValueType::Primitive(ty) => Some((ty, None)),
+        ValueType::Named(type_name) if type_name == "usize" => Some((layout.pointer_type(), None)),
+        ValueType::Named(type_name) => match enums.get(&type_name) {
+            Some((ty, variants)) => Some((*ty, Some(variants.clone()))),
+            None => {
+                println!("Failed to find enum {type_name} used by {name}");
+                None
             }
+        },
+    };
+
+    for (index, sym) in symbols {
+        let entry = elf.symbol_by_index(index).unwrap();
+        let symbol_address = entry.address().wrapping_add(bias);
+        let do_math = |name: &str, math_ctx_variable_name: &str, expr_str| match expr_str {
+            // The macros already check this, so this only fails for firmware built with an older
+            // version
+            Some(expr_str) => Some(
+                parse_expr(expr_str, math_ctx_variable_name)
+                    .unwrap_or_else(|e| panic!("For metric: {name}, {e}")),
+            ),
             None => None,
         };
 
+        // Structs are shown as one metric per field, returns the fields with their offsets within
+        // the struct or `None` if `type_name` is not a struct
+        let struct_fields = |name: &str,
+                             type_name: &str,
+                             address: u64,
+                             meta: &Metadata|
+         -> Option<Vec<(u64, Metric)>> {
+            let mut flattened = Vec::new();
+            flatten_struct(name, structs.get(type_name)?, 0, &structs, &mut flattened);
+            let mut fields = Vec::new();
+            for (name, ty, offset) in flattened {
+                let Some((ty, variants)) = resolve_type(&name, ty) else {
+                    continue;
+                };
+                let math_ctx_variable_name = name.replace('.', "__");
+                let expr = Some(
+                    parse_expr(&math_ctx_variable_name, &math_ctx_variable_name)
+                        .unwrap_or_else(|e| panic!("For metric: {name}, {e}")),
+                );
+                let metric = Metric {
+                    name,
+                    math_ctx_variable_name,
+                    expr,
+                    ty,
+                    variants,
+                    // The fields are stored as is, not as `probe_plotter::metric::Wide`
+                    address: Address::Fixed(address + offset),
+                    last_value: f64::NAN,
+                    is_set: false,
+                    // Every field would be drawn in the same color otherwise
+                    meta: Metadata {
+                        color: None,
+                        ..meta.clone()
+                    },
+                };
+                fields.push((offset, metric));
+            }
+            Some(fields)
+        };
+
         // TODO: Why does this assert not succeed?
         //assert_eq!(entry.size(), 4);
         match sym {
@@ -178,18 +756,51 @@ pub fn parse(elf_bytes: &[u8]) -> (Vec<Metric>, Vec<Setting>, rtt::ScanRegion) {
                 expr,
                 ty,
                 address,
+                meta,
             } => {
+                if let ValueType::Named(type_name) = &ty
+                    && let Some(fields) = struct_fields(&name, type_name, symbol_address, &meta)
+                {
+                    metrics.extend(fields.into_iter().map(|(_offset, m)| m));
+                    continue;
+                }
+
+                let Some((ty, variants)) = resolve_type(&name, ty) else {
+                    continue;
+                };
                 let math_ctx_variable_name = name.replace('.', "__");
                 let expr = do_math(&name, &math_ctx_variable_name, expr.as_deref());
                 let address = match address {
-                    symbol::Address::Symbols => Address::Fixed(entry.address()),
-                    symbol::Address::Hardcoded { address } => Address::Fixed(address),
+                    symbol::Address::Symbols => Address::Storage(symbol_address, layout),
+                    symbol::Address::Hardcoded {
+                        address,
+                        bits: None,
+                    } => Address::Fixed(address),
+                    symbol::Address::Hardcoded {
+                        address,
+                        bits: Some(bits),
+                    } => Address::Bitfield { address, bits },
+                    // The static of the record refers to the plotted one, see
+                    // `probe_plotter::metric::SymbolRef`
+                    symbol::Address::Symbol { .. } => match read_pointer(&elf, &entry, layout) {
+                        Some(address) => Address::Fixed(address.wrapping_add(bias)),
+                        None => {
+                            println!("Failed to read the address of the static of {name}");
+                            continue;
+                        }
+                    },
                     symbol::Address::RelativeBaseMetricWithOffset {
                         base_metric,
                         offset,
                     } => Address::BaseWithOffset {
                         base_expression: ShuntingParser::parse_str(&base_metric).unwrap(),
-                        offset,
+                        offsets: vec![offset],
+                        layout,
+                    },
+                    symbol::Address::PointerChain { base_metric, .. } => Address::BaseWithOffset {
+                        base_expression: ShuntingParser::parse_str(&base_metric).unwrap(),
+                        offsets: pointer_chains[&name].clone(),
+                        layout,
                     },
                 };
                 metrics.push(Metric {
@@ -197,9 +808,36 @@ pub fn parse(elf_bytes: &[u8]) -> (Vec<Metric>, Vec<Setting>, rtt::ScanRegion) {
                     math_ctx_variable_name,
                     expr,
                     ty,
+                    variants,
                     address,
                     last_value: f64::NAN,
                     is_set: false,
+                    meta,
+                });
+            }
+            Symbol::MetricArray {
+                name,
+                expr,
+                ty,
+                len,
+                meta,
+            } => {
+                let Some((ty, _variants)) = resolve_type(&name, ty) else {
+                    continue;
+                };
+                let math_ctx_variable_name = name.replace('.', "__");
+                let expr = do_math(&name, &math_ctx_variable_name, expr.as_deref());
+                metric_arrays.push(MetricArray {
+                    name,
+                    math_ctx_variable_name,
+                    expr,
+                    ty,
+                    len,
+                    address: symbol_address,
+                    layout,
+                    raw_values: None,
+                    last_values: Vec::new(),
+                    meta,
                 });
             }
             Symbol::Setting {
@@ -207,16 +845,83 @@ pub fn parse(elf_bytes: &[u8]) -> (Vec<Metric>, Vec<Setting>, rtt::ScanRegion) {
                 ty,
                 range,
                 step_size,
+                address,
+                meta,
             } => {
-                settings.push(Setting {
+                let Some((ty, variants)) = resolve_type(&name, ty) else {
+                    continue;
+                };
+                let (address, hardcoded, bits) = match address {
+                    symbol::Address::Symbols => (symbol_address, false, None),
+                    symbol::Address::Hardcoded { address, bits } => (address, true, bits),
+                    _ => {
+                        println!("Unsupported address of setting {name}");
+                        continue;
+                    }
+                };
+                let range = match &variants {
+                    Some(variants) => {
+                        let discriminants = || variants.iter().map(|(_name, d)| *d);
+                        let min = discriminants().min().unwrap_or(0);
+                        let max = discriminants().max().unwrap_or(0);
+                        min as f64..=max as f64
+                    }
+                    None => range,
+                };
+                let mut setting =
+                    Setting::new(name, ty, variants, address, layout, range, step_size);
+                setting.hardcoded = hardcoded;
+                setting.bits = bits;
+                setting.meta = meta;
+                settings.push(setting);
+            }
+            Symbol::Action { name } => {
+                actions.push(Action::new(name, symbol_address));
+            }
+            Symbol::Registry { name, capacity } => {
+                registries.push(Registry::new(name, symbol_address, capacity, layout));
+            }
+            Symbol::MetricGroup { name, ty, meta } => {
+                // The metrics of the fields point into the first buffer, the group reads them from
+                // the buffer with the last commit
+                let buffers_address = symbol_address + metric_group::BUFFERS_OFFSET;
+                let fields = match &ty {
+                    ValueType::Named(type_name) => {
+                        struct_fields(&name, type_name, buffers_address, &meta)
+                    }
+                    ValueType::Primitive(_) => None,
+                };
+                let Some(fields) = fields else {
+                    println!(
+                        "The type {ty} of metric group {name} is not a struct with #[derive(Plottable)]"
+                    );
+                    continue;
+                };
+                let Some(frequency) = frequency else {
+                    no_frequency();
+                    continue;
+                };
+
+                let size = entry.size().saturating_sub(metric_group::BUFFERS_OFFSET) / 2;
+                if let Some((offset, m)) = fields
+                    .iter()
+                    .find(|(offset, m)| offset + raw_size(m.ty) > size)
+                {
+                    println!(
+                        "Field {} at offset {offset} is outside of metric group {name} of size {size}",
+                        m.name
+                    );
+                    continue;
+                }
+                metric_groups.push(MetricGroup::new(
                     name,
-                    ty,
-                    address: entry.address(),
-                    value: f64::NAN,
-                    range,
-                    step_size,
-                });
+                    symbol_address,
+                    size,
+                    frequency,
+                    fields,
+                ));
             }
+            Symbol::Enum { .. } | Symbol::Struct { .. } => {}
         }
     }
 
@@ -229,11 +934,21 @@ pub fn parse(elf_bytes: &[u8]) -> (Vec<Metric>, Vec<Setting>, rtt::ScanRegion) {
     }
     println!("{metrics:?}");
 
-    (metrics, settings, scan_region)
+    Ok(Parsed {
+        metrics,
+        metric_arrays,
+        settings,
+        actions,
+        registries,
+        metric_groups,
+        scan_region,
+        stream,
+    })
 }
 
-/// Parse elf file into a set of Metrics and Settings
-pub fn parse_elf_file(elf_path: &str) -> (Vec<Metric>, Vec<Setting>, rtt::ScanRegion) {
+/// Parse elf file into a set of Metrics, MetricArrays, Settings, Actions, Registries and
+/// MetricGroups
+pub fn parse_elf_file(elf_path: &str) -> Result<Parsed, IncompatibleVersion> {
     let mut buffer = Vec::new();
     std::fs::File::open(elf_path)
         .unwrap()
@@ -247,24 +962,25 @@ pub fn parse_elf_file(elf_path: &str) -> (Vec<Metric>, Vec<Setting>, rtt::ScanRe
 ///
 /// This handles
 /// * defmt logging
-/// * reading metrics
+/// * reading metrics and metric arrays
+/// * receiving samples sent with `Metric::push`
+/// * reading the last commit of metric groups
+/// * rescanning registries for metrics added or removed at runtime
 /// * reading initial values for settings
-/// * writing updated settings
-#[allow(clippy::too_many_arguments)]
+/// * writing updated settings and reporting whether the target applied them
+/// * pressing actions
 pub fn probe_background_thread(
     update_rate: Duration,
     channel_mode: Option<ChannelMode>,
     target: &str,
     elf_bytes: &[u8],
-    mut settings: Vec<Setting>,
-    mut metrics: Vec<Metric>,
-    scan_region: rtt::ScanRegion,
-    settings_update_receiver: mpsc::Receiver<Setting>,
-    initial_settings_sender: mpsc::Sender<Vec<Setting>>,
+    parsed: Parsed,
+    settings_update_receiver: mpsc::Receiver<Control>,
+    settings_sender: mpsc::Sender<Vec<Control>>,
 ) {
     let mut session = probe_rs::Session::auto_attach(target, Default::default()).unwrap();
     let mut core = session.core(0).unwrap();
-    let mut rtt = Rtt::attach_region(&mut core, &scan_region).unwrap();
+    let mut rtt = Rtt::attach_region(&mut core, &parsed.scan_region).unwrap();
     let table = defmt_decoder::Table::parse(elf_bytes).unwrap().unwrap();
 
     // TODO: Get this to work
@@ -331,6 +1047,15 @@ pub fn probe_background_thread(
         }
     };
 
+    // The samples of `Metric::push` have a channel of their own, in case defmt was found by
+    // scanning for a control block instead of by its symbol
+    rtt.up_channels
+        .retain(|ch| ch.name() != Some(stream::CHANNEL_NAME));
+    let stream_channel = parsed
+        .stream
+        .as_ref()
+        .map(|stream| stream.attach(&mut core).unwrap());
+
     if let Some(channel_mode) = channel_mode {
         for ch in &mut rtt.up_channels {
             ch.set_mode(&mut core, channel_mode).unwrap();
@@ -343,27 +1068,124 @@ pub fn probe_background_thread(
         .map(|_| table.new_stream_decoder())
         .collect();
 
+    update_loop(
+        &mut core,
+        &rec,
+        update_rate,
+        parsed,
+        stream_channel,
+        settings_update_receiver,
+        settings_sender,
+        |core| {
+            receive_defmt_messages(&mut rtt, core, &mut decoders);
+            log_defmt_messages(&rec, &locs, &mut decoders);
+        },
+    );
+}
+
+/// Send the initial values of the settings, then keep reading metrics and writing settings
+///
+/// Settings and actions are sent again through `settings_sender` once the target has handled a
+/// write or press
+///
+/// `stream_channel` is the channel to receive the samples of `parsed.stream` from, and `poll` is
+/// called once every update for anything else to do with the target
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn update_loop<M: Memory>(
+    memory: &mut M,
+    rec: &rerun::RecordingStream,
+    update_rate: Duration,
+    parsed: Parsed,
+    stream_channel: Option<impl Channel<M>>,
+    settings_update_receiver: mpsc::Receiver<Control>,
+    settings_sender: mpsc::Sender<Vec<Control>>,
+    mut poll: impl FnMut(&mut M),
+) {
+    let Parsed {
+        mut metrics,
+        mut metric_arrays,
+        settings,
+        actions,
+        mut registries,
+        mut metric_groups,
+        scan_region: _,
+        stream,
+    } = parsed;
+    let mut stream = stream.zip(stream_channel);
+
+    let mut controls: Vec<_> = settings
+        .into_iter()
+        .map(Control::Setting)
+        .chain(actions.into_iter().map(Control::Action))
+        .collect();
+
     // Load initial values from device
-    for setting in &mut settings {
-        setting.read(&mut core).unwrap();
+    for control in &mut controls {
+        control.read(memory).unwrap();
     }
 
     // Send initial settings back to main thread
-    initial_settings_sender.send(settings).unwrap();
+    settings_sender.send(controls.clone()).unwrap();
+
+    let group_metrics = metric_groups
+        .iter()
+        .flat_map(|g| g.fields.iter().map(|(_, m)| m));
+    for m in metrics.iter().chain(group_metrics) {
+        m.log_style(rec);
+    }
 
     let mut math_ctx = MathContext::new();
     loop {
-        for mut setting in settings_update_receiver.try_iter() {
-            setting.write(setting.value, &mut core).unwrap();
+        for update in settings_update_receiver.try_iter() {
+            if let Some(control) = controls.iter_mut().find(|c| c.name() == update.name()) {
+                control.apply(&update, memory).unwrap();
+            }
         }
 
-        receive_defmt_messages(&mut rtt, &mut core, &mut decoders);
-        log_defmt_messages(&rec, &locs, &mut decoders);
+        let mut handled = Vec::new();
+        for control in &mut controls {
+            if control.poll_status(memory).unwrap() {
+                handled.push(control.clone());
+            }
+        }
+        if !handled.is_empty() {
+            settings_sender.send(handled).unwrap();
+        }
 
-        for m in &mut metrics {
-            m.read(&mut core, &mut math_ctx).unwrap();
+        poll(memory);
+
+        if let Some((stream, channel)) = &mut stream {
+            stream
+                .receive(memory, channel, rec, &mut metrics, &mut math_ctx)
+                .unwrap();
+        }
+
+        for g in &mut metric_groups {
+            g.update(memory, rec, &mut math_ctx).unwrap();
+        }
+
+        for r in &mut registries {
+            for name in r.rescan(memory).unwrap() {
+                rec.log(name, &rerun::Clear::flat()).unwrap();
+            }
+        }
+
+        let dynamic_metrics = registries.iter_mut().flat_map(|r| &mut r.metrics);
+        for m in metrics.iter_mut().chain(dynamic_metrics) {
+            if stream.as_ref().is_some_and(|(s, _)| s.is_streamed(m)) {
+                continue;
+            }
+            m.read(memory, &mut math_ctx).unwrap();
+            let previous = m.last_value;
             if let Some((x, _s)) = m.compute(&mut math_ctx) {
-                rec.log(m.name.clone(), &rerun::Scalars::single(x)).unwrap();
+                m.log(rec, x, previous);
+            }
+        }
+
+        for a in &mut metric_arrays {
+            a.read(memory).unwrap();
+            if let Some((values, Status::New)) = a.compute(&mut math_ctx) {
+                a.log(rec, &values);
             }
         }
         std::thread::sleep(update_rate);
@@ -0,0 +1,277 @@
+//! Plot metrics and edit settings of the current process, without any probe
+//!
+//! With the `std` feature of `probe-plotter`, `make_metric!` and friends can be used in desktop
+//! simulations and unit tests. The metrics and settings are found the same way as on a real
+//! target, by parsing the symbols of the executable, which is then read in-process. Only Linux is
+//! supported for now since the mapped memory is found using `/proc/self/maps`.
+//!
+//! ```no_run
+//! std::thread::spawn(|| {
+//!     let mut sawtooth = probe_plotter::make_metric!(SAWTOOTH: i32 = 0).unwrap();
+//!     for i in 0.. {
+//!         sawtooth.set(i % 100);
+//!         std::thread::sleep(std::time::Duration::from_millis(1));
+//!     }
+//! });
+//!
+//! probe_plotter_tools::local::run_viewer(std::time::Duration::from_millis(10)).unwrap();
+//! ```
+
+use std::{ops::Range, sync::mpsc, time::Duration};
+
+use object::{Object, ObjectSymbol};
+
+use crate::{
+    Address, Parsed, gui, memory::Memory, parse_with_bias, setting::Control, stream::Stream,
+    update_loop,
+};
+
+/// Used to find out where the executable has been loaded
+#[used]
+#[unsafe(export_name = "probe_plotter_tools_anchor")]
+static ANCHOR: u8 = 0;
+
+/// Memory of the current process
+///
+/// Only addresses which are mapped are accessed, anything else results in an error
+pub struct LocalMemory {
+    readable: Vec<Range<u64>>,
+    writable: Vec<Range<u64>>,
+}
+
+impl LocalMemory {
+    pub fn new() -> std::io::Result<Self> {
+        let mut memory = LocalMemory {
+            readable: Vec::new(),
+            writable: Vec::new(),
+        };
+        memory.update_mappings()?;
+        Ok(memory)
+    }
+
+    /// Read the memory mappings of the process from `/proc/self/maps`
+    fn update_mappings(&mut self) -> std::io::Result<()> {
+        self.readable.clear();
+        self.writable.clear();
+        for line in std::fs::read_to_string("/proc/self/maps")?.lines() {
+            let mut parts = line.split_whitespace();
+            let (Some(range), Some(permissions)) = (parts.next(), parts.next()) else {
+                continue;
+            };
+            let Some((start, end)) = range.split_once('-') else {
+                continue;
+            };
+            let (Ok(start), Ok(end)) =
+                (u64::from_str_radix(start, 16), u64::from_str_radix(end, 16))
+            else {
+                continue;
+            };
+
+            if permissions.starts_with('r') {
+                self.readable.push(start..end);
+            }
+            if permissions[1..].starts_with('w') {
+                self.writable.push(start..end);
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether `len` bytes at `address` are mapped
+    pub fn is_mapped(&self, address: u64, len: usize, write: bool) -> bool {
+        let ranges = if write {
+            &self.writable
+        } else {
+            &self.readable
+        };
+        let end = address.saturating_add(len as u64);
+        ranges.iter().any(|r| r.start <= address && end <= r.end)
+    }
+
+    fn check<T>(&mut self, address: u64, write: bool) -> Result<(), probe_rs::Error> {
+        if !address.is_multiple_of(align_of::<T>() as u64) {
+            return Err(probe_rs::Error::Other(format!(
+                "Address {address:#x} is not aligned"
+            )));
+        }
+
+        if !self.is_mapped(address, size_of::<T>(), write) {
+            // The memory may have been mapped since we last looked
+            self.update_mappings()
+                .map_err(|e| probe_rs::Error::Other(e.to_string()))?;
+            if !self.is_mapped(address, size_of::<T>(), write) {
+                return Err(probe_rs::Error::Other(format!(
+                    "Address {address:#x} is not mapped"
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    fn read_word<T: Copy>(&mut self, address: u64) -> Result<T, probe_rs::Error> {
+        self.check::<T>(address, false)?;
+        // Safety: The address is aligned and mapped. The target may write at the same time, same
+        // as when reading through a probe.
+        Ok(unsafe { (address as *const T).read_volatile() })
+    }
+
+    fn write_word<T: Copy>(&mut self, address: u64, data: T) -> Result<(), probe_rs::Error> {
+        self.check::<T>(address, true)?;
+        // Safety: See `read_word`
+        unsafe { (address as *mut T).write_volatile(data) };
+        Ok(())
+    }
+}
+
+impl Memory for LocalMemory {
+    fn read_word_8(&mut self, address: u64) -> Result<u8, probe_rs::Error> {
+        self.read_word(address)
+    }
+
+    fn read_word_16(&mut self, address: u64) -> Result<u16, probe_rs::Error> {
+        self.read_word(address)
+    }
+
+    fn read_word_32(&mut self, address: u64) -> Result<u32, probe_rs::Error> {
+        self.read_word(address)
+    }
+
+    fn read_word_64(&mut self, address: u64) -> Result<u64, probe_rs::Error> {
+        self.read_word(address)
+    }
+
+    fn read_32(&mut self, address: u64, data: &mut [u32]) -> Result<(), probe_rs::Error> {
+        for (i, x) in data.iter_mut().enumerate() {
+            *x = self.read_word(address + i as u64 * 4)?;
+        }
+        Ok(())
+    }
+
+    fn read(&mut self, address: u64, data: &mut [u8]) -> Result<(), probe_rs::Error> {
+        for (i, x) in data.iter_mut().enumerate() {
+            *x = self.read_word(address + i as u64)?;
+        }
+        Ok(())
+    }
+
+    fn write_word_8(&mut self, address: u64, data: u8) -> Result<(), probe_rs::Error> {
+        self.write_word(address, data)
+    }
+
+    fn write_word_16(&mut self, address: u64, data: u16) -> Result<(), probe_rs::Error> {
+        self.write_word(address, data)
+    }
+
+    fn write_word_32(&mut self, address: u64, data: u32) -> Result<(), probe_rs::Error> {
+        self.write_word(address, data)
+    }
+
+    fn write_word_64(&mut self, address: u64, data: u64) -> Result<(), probe_rs::Error> {
+        self.write_word(address, data)
+    }
+
+    fn write_32(&mut self, address: u64, data: &[u32]) -> Result<(), probe_rs::Error> {
+        for (i, x) in data.iter().enumerate() {
+            self.write_word(address + i as u64 * 4, *x)?;
+        }
+        Ok(())
+    }
+
+    /// Any writable mapping, which includes the stack and heap
+    fn is_ram(&mut self, address: u64, len: u64) -> bool {
+        if !self.is_mapped(address, len as usize, true) {
+            // The memory may have been mapped since we last looked
+            if self.update_mappings().is_err() {
+                return false;
+            }
+        }
+        self.is_mapped(address, len as usize, true)
+    }
+}
+
+/// Parse the executable of the current process into a set of Metrics, MetricArrays, Settings,
+/// Actions, Registries, MetricGroups and the Stream with the addresses they have in this process
+///
+/// There is no RTT in this process, so the scan region is of no use.
+pub fn parse_current_exe() -> Parsed {
+    let elf_bytes = std::fs::read(std::env::current_exe().unwrap()).unwrap();
+
+    // The executable may be loaded at a different address than the one in the file
+    let elf = object::File::parse(&*elf_bytes).unwrap();
+    let anchor = elf
+        .symbols()
+        .find(|s| s.name() == Ok("probe_plotter_tools_anchor"))
+        .expect("Failed to find symbols, make sure the executable is not stripped");
+    let bias = (&raw const ANCHOR as u64).wrapping_sub(anchor.address());
+
+    // Built together with this crate, so the versions always match
+    parse_with_bias(&elf_bytes, bias).unwrap_or_else(|e| panic!("{e}"))
+}
+
+/// Like [crate::probe_background_thread] but for the current process
+///
+/// This handles
+/// * reading metrics and metric arrays
+/// * receiving samples sent with `Metric::push`
+/// * reading the last commit of metric groups
+/// * rescanning registries for metrics added or removed at runtime
+/// * reading initial values for settings
+/// * writing updated settings and reporting whether the target applied them
+/// * pressing actions
+pub fn local_background_thread(
+    update_rate: Duration,
+    settings_update_receiver: mpsc::Receiver<Control>,
+    settings_sender: mpsc::Sender<Vec<Control>>,
+) {
+    let mut parsed = parse_current_exe();
+    let mut memory = LocalMemory::new().unwrap();
+
+    // Such as `make_metric_from_address!` with addresses of a peripheral on the real target
+    parsed.metrics.retain(|m| match m.address {
+        Address::Fixed(address) | Address::Bitfield { address, .. }
+            if !memory.is_mapped(address, 1, false) =>
+        {
+            println!("Skipping {} at {address:#x} which is not mapped", m.name);
+            false
+        }
+        _ => true,
+    });
+    parsed.settings.retain(|s| {
+        let mapped = !s.hardcoded || memory.is_mapped(s.address, 1, true);
+        if !mapped {
+            println!(
+                "Skipping {} at {:#x} which is not mapped",
+                s.name, s.address
+            );
+        }
+        mapped
+    });
+
+    // There is no probe to attach to the RTT control block with
+    let stream_channel = parsed.stream.as_ref().map(Stream::memory_channel);
+
+    let rec = rerun::RecordingStreamBuilder::new("probe-plotter")
+        .spawn()
+        .unwrap();
+
+    update_loop(
+        &mut memory,
+        &rec,
+        update_rate,
+        parsed,
+        stream_channel,
+        settings_update_receiver,
+        settings_sender,
+        |_memory| {},
+    );
+}
+
+/// Show the metrics and settings of the current process in the custom viewer
+///
+/// This blocks until the viewer is closed. It must be called from the main thread, so run the code
+/// using the metrics and settings in an other thread.
+pub fn run_viewer(update_rate: Duration) -> Result<(), Box<dyn std::error::Error>> {
+    gui::run_viewer(move |settings_update_receiver, settings_sender| {
+        local_background_thread(update_rate, settings_update_receiver, settings_sender)
+    })
+}
@@ -0,0 +1,75 @@
+use probe_rs::{MemoryInterface, config::MemoryRegion};
+
+/// Memory of the target, accessed through a debug probe or in-process, see [crate::local]
+pub trait Memory {
+    fn read_word_8(&mut self, address: u64) -> Result<u8, probe_rs::Error>;
+    fn read_word_16(&mut self, address: u64) -> Result<u16, probe_rs::Error>;
+    fn read_word_32(&mut self, address: u64) -> Result<u32, probe_rs::Error>;
+    fn read_word_64(&mut self, address: u64) -> Result<u64, probe_rs::Error>;
+    fn read_32(&mut self, address: u64, data: &mut [u32]) -> Result<(), probe_rs::Error>;
+    fn read(&mut self, address: u64, data: &mut [u8]) -> Result<(), probe_rs::Error>;
+
+    fn write_word_8(&mut self, address: u64, data: u8) -> Result<(), probe_rs::Error>;
+    fn write_word_16(&mut self, address: u64, data: u16) -> Result<(), probe_rs::Error>;
+    fn write_word_32(&mut self, address: u64, data: u32) -> Result<(), probe_rs::Error>;
+    fn write_word_64(&mut self, address: u64, data: u64) -> Result<(), probe_rs::Error>;
+    fn write_32(&mut self, address: u64, data: &[u32]) -> Result<(), probe_rs::Error>;
+
+    /// Whether `len` bytes at `address` are RAM, used to check pointers read from the target
+    /// before following them
+    fn is_ram(&mut self, address: u64, len: u64) -> bool;
+}
+
+impl Memory for probe_rs::Core<'_> {
+    fn read_word_8(&mut self, address: u64) -> Result<u8, probe_rs::Error> {
+        MemoryInterface::read_word_8(self, address)
+    }
+
+    fn read_word_16(&mut self, address: u64) -> Result<u16, probe_rs::Error> {
+        MemoryInterface::read_word_16(self, address)
+    }
+
+    fn read_word_32(&mut self, address: u64) -> Result<u32, probe_rs::Error> {
+        MemoryInterface::read_word_32(self, address)
+    }
+
+    fn read_word_64(&mut self, address: u64) -> Result<u64, probe_rs::Error> {
+        MemoryInterface::read_word_64(self, address)
+    }
+
+    fn read_32(&mut self, address: u64, data: &mut [u32]) -> Result<(), probe_rs::Error> {
+        MemoryInterface::read_32(self, address, data)
+    }
+
+    fn read(&mut self, address: u64, data: &mut [u8]) -> Result<(), probe_rs::Error> {
+        MemoryInterface::read(self, address, data)
+    }
+
+    fn write_word_8(&mut self, address: u64, data: u8) -> Result<(), probe_rs::Error> {
+        MemoryInterface::write_word_8(self, address, data)
+    }
+
+    fn write_word_16(&mut self, address: u64, data: u16) -> Result<(), probe_rs::Error> {
+        MemoryInterface::write_word_16(self, address, data)
+    }
+
+    fn write_word_32(&mut self, address: u64, data: u32) -> Result<(), probe_rs::Error> {
+        MemoryInterface::write_word_32(self, address, data)
+    }
+
+    fn write_word_64(&mut self, address: u64, data: u64) -> Result<(), probe_rs::Error> {
+        MemoryInterface::write_word_64(self, address, data)
+    }
+
+    fn write_32(&mut self, address: u64, data: &[u32]) -> Result<(), probe_rs::Error> {
+        MemoryInterface::write_32(self, address, data)
+    }
+
+    fn is_ram(&mut self, address: u64, len: u64) -> bool {
+        let end = address.saturating_add(len);
+        self.memory_regions().any(|region| match region {
+            MemoryRegion::Ram(ram) => ram.range.start <= address && end <= ram.range.end,
+            _ => false,
+        })
+    }
+}
@@ -1,16 +1,21 @@
+use probe_plotter_common::symbol::Metadata;
 use shunting::MathContext;
 use std::fmt;
 
-use crate::{Address, Type, read_value};
+use crate::{Address, Layout, Type, Variants, memory::Memory, read_bits, read_storage, read_value};
 
 pub struct Metric {
     pub name: String,
     pub math_ctx_variable_name: String,
     pub expr: Option<shunting::RPNExpr>,
     pub ty: Type,
+
+    /// Set if the metric is an enum
+    pub variants: Option<Variants>,
     pub address: Address,
     pub last_value: f64,
     pub is_set: bool,
+    pub meta: Metadata,
 }
 
 impl fmt::Debug for Metric {
@@ -19,11 +24,48 @@ impl fmt::Debug for Metric {
             .field("name", &self.name)
             .field("expr", &self.expr)
             .field("ty", &self.ty)
+            .field("variants", &self.variants)
             .field("address", &self.address)
+            .field("meta", &self.meta)
             .finish()
     }
 }
 
+/// Add the first offset to `base`, then for every further offset read the pointer at the address
+/// so far and add the offset to that
+///
+/// Returns `None` if any pointer, or the `size` bytes at the final address, is outside of RAM.
+pub fn follow_pointers(
+    core: &mut impl Memory,
+    base: u64,
+    offsets: &[u64],
+    size: u64,
+    layout: Layout,
+) -> Result<Option<u64>, probe_rs::Error> {
+    let pointer_size = layout.pointer_size();
+
+    let mut address = base;
+    for (i, offset) in offsets.iter().enumerate() {
+        let pointer = if i == 0 {
+            base
+        } else if core.is_ram(address, pointer_size) {
+            match pointer_size {
+                8 => core.read_word_64(address)?,
+                _ => core.read_word_32(address)? as u64,
+            }
+        } else {
+            return Ok(None);
+        };
+
+        if !core.is_ram(pointer, 1) {
+            return Ok(None);
+        }
+        address = pointer.wrapping_add(*offset);
+    }
+
+    Ok(core.is_ram(address, size).then_some(address))
+}
+
 pub enum Status {
     SameAsLast,
     New,
@@ -32,14 +74,19 @@ pub enum Status {
 impl Metric {
     pub fn read(
         &mut self,
-        core: &mut probe_rs::Core,
+        core: &mut impl Memory,
         math_ctx: &mut MathContext,
     ) -> Result<(), probe_rs::Error> {
-        let address = match &self.address {
-            Address::Fixed(a) => *a,
+        let x = match &self.address {
+            Address::Fixed(a) => read_value(core, *a, self.ty)?,
+            Address::Bitfield { address, bits } => {
+                Some(read_bits(core, *address, self.ty, *bits)? as f64)
+            }
+            Address::Storage(a, layout) => read_storage(core, *a, self.ty, *layout)?,
             Address::BaseWithOffset {
                 base_expression,
-                offset,
+                offsets,
+                layout,
             } => {
                 let a = match math_ctx.eval(base_expression) {
                     Ok(0.0) => {
@@ -52,17 +99,84 @@ impl Metric {
                     }
                     Ok(a) => a,
                 };
-                a as u64 + offset
+                let size = if self.ty.is_wide() {
+                    8
+                } else {
+                    self.ty.storage_size()
+                };
+                let Some(a) = follow_pointers(core, a as u64, offsets, size, *layout)? else {
+                    // Pointing outside of RAM, likely not yet initialized
+                    return Ok(());
+                };
+                read_value(core, a, self.ty)?
             }
         };
 
-        let x = read_value(core, address, self.ty)?;
+        let Some(x) = x else {
+            // No consistent value could be read, try again next time
+            return Ok(());
+        };
         math_ctx.setvar(&self.math_ctx_variable_name, shunting::MathOp::Number(x));
         self.is_set = true;
 
         Ok(())
     }
 
+    /// Digital values such as bools and enums are plotted as steps rather than interpolated lines
+    pub fn is_digital(&self) -> bool {
+        let is_flag =
+            matches!(self.address, Address::Bitfield { bits, .. } if bits.high == bits.low);
+        self.ty == Type::bool || self.variants.is_some() || is_flag
+    }
+
+    /// Where the metric is logged in rerun, below its group if it has one
+    pub fn entity_path(&self) -> String {
+        match &self.meta.group {
+            Some(group) => format!("{group}/{}", self.name),
+            None => self.name.clone(),
+        }
+    }
+
+    /// Log the name with unit and the color of the plot, once before any values
+    pub fn log_style(&self, rec: &rerun::RecordingStream) {
+        if self.meta.unit.is_none() && self.meta.color.is_none() {
+            return;
+        }
+
+        let name = match &self.meta.unit {
+            Some(unit) => format!("{} [{unit}]", self.name),
+            None => self.name.clone(),
+        };
+        let mut style = rerun::SeriesLines::new().with_names([name]);
+        if let Some([r, g, b]) = self.meta.rgb() {
+            style = style.with_colors([rerun::Color::from_rgb(r, g, b)]);
+        }
+        rec.log_static(self.entity_path(), &style).unwrap();
+    }
+
+    /// Log the value computed by [Self::compute] to rerun
+    ///
+    /// `previous` is the value from before the call to [Self::compute]
+    pub fn log(&self, rec: &rerun::RecordingStream, x: f64, previous: f64) {
+        if self.is_digital() && x != previous && !previous.is_nan() {
+            // Repeat the previous value right before the new one to draw a step
+            rec.log(self.entity_path(), &rerun::Scalars::single(previous))
+                .unwrap();
+        }
+        rec.log(self.entity_path(), &rerun::Scalars::single(x))
+            .unwrap();
+
+        if let Some(variants) = &self.variants
+            && x != previous
+        {
+            let text = variant_name(variants, x)
+                .map(str::to_owned)
+                .unwrap_or_else(|| format!("<invalid variant {x}>"));
+            rec.log(self.entity_path(), &rerun::TextLog::new(text))
+                .unwrap();
+        }
+    }
+
     pub fn compute(&mut self, math_ctx: &mut MathContext) -> Option<(f64, Status)> {
         let Some(expr) = &self.expr else {
             return None;
@@ -82,3 +196,11 @@ impl Metric {
         Some((new, status))
     }
 }
+
+/// Name of the variant with discriminant `x`
+pub fn variant_name(variants: &Variants, x: f64) -> Option<&str> {
+    variants
+        .iter()
+        .find(|(_name, discriminant)| *discriminant as f64 == x)
+        .map(|(name, _discriminant)| name.as_str())
+}
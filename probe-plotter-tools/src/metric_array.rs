@@ -0,0 +1,119 @@
+use probe_plotter_common::symbol::Metadata;
+use shunting::MathContext;
+use std::fmt;
+
+use crate::{Layout, Type, memory::Memory, metric::Status, value_from_bytes};
+
+/// Number of attempts to get a consistent snapshot before giving up until the next update
+const READ_ATTEMPTS: usize = 4;
+
+pub struct MetricArray {
+    pub name: String,
+    pub math_ctx_variable_name: String,
+    pub expr: Option<shunting::RPNExpr>,
+
+    /// Type of every element
+    pub ty: Type,
+
+    /// Number of elements
+    pub len: u64,
+    pub address: u64,
+    pub layout: Layout,
+
+    /// Last consistent snapshot of the raw values
+    pub raw_values: Option<Vec<f64>>,
+    pub last_values: Vec<f64>,
+    pub meta: Metadata,
+}
+
+impl fmt::Debug for MetricArray {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MetricArray")
+            .field("name", &self.name)
+            .field("expr", &self.expr)
+            .field("ty", &self.ty)
+            .field("len", &self.len)
+            .field("address", &self.address)
+            .field("meta", &self.meta)
+            .finish()
+    }
+}
+
+impl MetricArray {
+    /// Read a snapshot of all elements
+    ///
+    /// The storage is laid out as `probe_plotter::metric_array::ArrayStorage`, the data is
+    /// surrounded by a pair of sequence counters which are equal when the snapshot is consistent.
+    pub fn read(&mut self, core: &mut impl Memory) -> Result<(), probe_rs::Error> {
+        let element_size = self.layout.storage_size(self.ty) as usize;
+        let data_size = self.len as usize * element_size;
+
+        // `probe_plotter::metric::Wide` is made of `u32`s, everything else is aligned to its size
+        let align = if self.layout.is_stored_wide(self.ty) {
+            4
+        } else {
+            element_size
+        };
+        let data_offset = 4usize.next_multiple_of(align);
+        let start_offset = (data_offset + data_size).next_multiple_of(4);
+        let mut bytes = vec![0; start_offset + 4];
+
+        for _ in 0..READ_ATTEMPTS {
+            core.read(self.address, &mut bytes)?;
+            let end = &bytes[..4];
+            let start = &bytes[start_offset..];
+            if end == start {
+                let values = bytes[data_offset..][..data_size]
+                    .chunks_exact(element_size)
+                    .map(|b| value_from_bytes(b, self.ty, self.layout))
+                    .collect();
+                self.raw_values = Some(values);
+                return Ok(());
+            }
+        }
+
+        // No consistent snapshot could be read, try again next time
+        Ok(())
+    }
+
+    /// Apply the expression to every element
+    pub fn compute(&mut self, math_ctx: &mut MathContext) -> Option<(Vec<f64>, Status)> {
+        let raw_values = self.raw_values.as_ref()?;
+
+        let new: Vec<f64> = match &self.expr {
+            Some(expr) => raw_values
+                .iter()
+                .map(|x| {
+                    math_ctx.setvar(&self.math_ctx_variable_name, shunting::MathOp::Number(*x));
+                    math_ctx.eval(expr).unwrap()
+                })
+                .collect(),
+            None => raw_values.clone(),
+        };
+
+        let status = if new == self.last_values {
+            Status::SameAsLast
+        } else {
+            Status::New
+        };
+        self.last_values = new.clone();
+        Some((new, status))
+    }
+
+    /// Where the metric array is logged in rerun, below its group if it has one
+    pub fn entity_path(&self) -> String {
+        match &self.meta.group {
+            Some(group) => format!("{group}/{}", self.name),
+            None => self.name.clone(),
+        }
+    }
+
+    /// Log a snapshot computed by [Self::compute] to rerun
+    pub fn log(&self, rec: &rerun::RecordingStream, values: &[f64]) {
+        let mut chart = rerun::BarChart::new(values);
+        if let Some([r, g, b]) = self.meta.rgb() {
+            chart = chart.with_color(rerun::Color::from_rgb(r, g, b));
+        }
+        rec.log(self.entity_path(), &chart).unwrap();
+    }
+}
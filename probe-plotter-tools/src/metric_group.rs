@@ -0,0 +1,125 @@
+use shunting::MathContext;
+
+use crate::{
+    WIDE_READ_ATTEMPTS, memory::Memory, metric::Metric, raw_value_from_bytes, stream::TIMELINE,
+};
+
+/// Offset of the first buffer within `probe_plotter::metric_group::GroupStorage`, after the commit
+/// counter and the timestamps
+pub const BUFFERS_OFFSET: u64 = 16;
+
+/// Struct whose fields are committed together by the target with `MetricGroup::commit`
+///
+/// The storage is laid out as `probe_plotter::metric_group::GroupStorage`. The target sets the
+/// values in one buffer while the host reads the other one, which is the one with the last commit.
+#[derive(Debug)]
+pub struct MetricGroup {
+    pub name: String,
+    pub address: u64,
+
+    /// Size of one buffer in bytes
+    pub size: u64,
+
+    /// Ticks per second of the timestamps
+    pub frequency: u64,
+
+    /// One metric per field, with the offset of the field within a buffer
+    pub fields: Vec<(u64, Metric)>,
+
+    /// Value of the commit counter the last time the group was read
+    commits: Option<u32>,
+
+    /// Timestamp of the last commit, the target only stores the lower 32 bits
+    timestamp: u64,
+}
+
+impl MetricGroup {
+    pub fn new(
+        name: String,
+        address: u64,
+        size: u64,
+        frequency: u64,
+        fields: Vec<(u64, Metric)>,
+    ) -> Self {
+        MetricGroup {
+            name,
+            address,
+            size,
+            frequency,
+            fields,
+            commits: None,
+            timestamp: 0,
+        }
+    }
+
+    /// Read the buffer and timestamp of the last commit
+    ///
+    /// The commit counter is read before and after the buffer, if they differ then the target may
+    /// have written to the buffer in the mean time. Returns `None` if nothing has been committed
+    /// since the last time or no consistent read was possible.
+    fn read_commit(
+        &mut self,
+        core: &mut impl Memory,
+    ) -> Result<Option<(u32, Vec<u8>)>, probe_rs::Error> {
+        let mut buffer = vec![0; self.size as usize];
+        for _ in 0..WIDE_READ_ATTEMPTS {
+            let commits = core.read_word_32(self.address)?;
+            if commits == 0 || self.commits == Some(commits) {
+                return Ok(None);
+            }
+
+            let index = (commits & 1) as u64;
+            let timestamp = core.read_word_32(self.address + 4 + index * 4)?;
+            core.read(
+                self.address + BUFFERS_OFFSET + index * self.size,
+                &mut buffer,
+            )?;
+
+            if core.read_word_32(self.address)? == commits {
+                self.commits = Some(commits);
+                return Ok(Some((timestamp, buffer)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Read the last commit and log all fields at its timestamp
+    pub fn update(
+        &mut self,
+        core: &mut impl Memory,
+        rec: &rerun::RecordingStream,
+        math_ctx: &mut MathContext,
+    ) -> Result<(), probe_rs::Error> {
+        let Some((timestamp, buffer)) = self.read_commit(core)? else {
+            return Ok(());
+        };
+
+        // Extend the timestamp assuming it wrapped around at most once since the last commit
+        self.timestamp += timestamp.wrapping_sub(self.timestamp as u32) as u64;
+
+        // All values are set before computing any, the expressions may refer to other fields.
+        // The fields are stored as is, not as `probe_plotter::metric::Wide`
+        for (offset, m) in &mut self.fields {
+            let x = raw_value_from_bytes(&buffer[*offset as usize..], m.ty);
+            math_ctx.setvar(&m.math_ctx_variable_name, shunting::MathOp::Number(x));
+            m.is_set = true;
+        }
+
+        let seconds = self.timestamp as f64 / self.frequency as f64;
+        rec.set_time(
+            TIMELINE,
+            rerun::TimeCell::from_duration_nanos((seconds * 1e9) as i64),
+        );
+        for (_offset, m) in &mut self.fields {
+            let previous = m.last_value;
+            if let Some((x, _s)) = m.compute(math_ctx) {
+                m.log(rec, x, previous);
+            }
+        }
+
+        // Values read from memory have no timestamp of the target
+        rec.disable_timeline(TIMELINE);
+
+        Ok(())
+    }
+}
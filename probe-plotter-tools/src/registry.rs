@@ -0,0 +1,127 @@
+use probe_plotter_common::symbol::Metadata;
+use shunting::ShuntingParser;
+
+use crate::{Address, Layout, Type, memory::Memory, metric::Metric};
+
+/// Size of `probe_plotter::registry::Entry` in words
+const ENTRY_WORDS: usize = 10;
+
+/// Offset of the first entry within `probe_plotter::registry::RegistryStorage`, the entries are
+/// 8 byte aligned
+const ENTRIES_OFFSET: usize = 8;
+
+/// Offset of the value within `probe_plotter::registry::Entry`
+const VALUE_OFFSET: u64 = 24;
+
+/// Metrics which are added and removed at runtime by the target
+#[derive(Debug)]
+pub struct Registry {
+    pub name: String,
+    pub address: u64,
+
+    /// Max number of metrics at the same time
+    pub capacity: u64,
+
+    /// Layout of the values, which are stored the same way as by `make_metric!`
+    pub layout: Layout,
+
+    /// Currently registered metrics
+    pub metrics: Vec<Metric>,
+
+    /// Value of the sequence counters the last time the entries were read
+    generation: Option<u32>,
+}
+
+impl Registry {
+    pub fn new(name: String, address: u64, capacity: u64, layout: Layout) -> Self {
+        Registry {
+            name,
+            address,
+            capacity,
+            layout,
+            metrics: Vec::new(),
+            generation: None,
+        }
+    }
+
+    /// Read all entries again if they have changed since the last time
+    ///
+    /// The storage is laid out as `probe_plotter::registry::RegistryStorage`, the entries are
+    /// surrounded by a pair of sequence counters which are equal when the entries are consistent.
+    /// Returns the names of the metrics which were removed.
+    pub fn rescan(&mut self, core: &mut impl Memory) -> Result<Vec<String>, probe_rs::Error> {
+        let end = core.read_word_32(self.address)?;
+        if self.generation == Some(end) {
+            return Ok(Vec::new());
+        }
+
+        let entries_words = self.capacity as usize * ENTRY_WORDS;
+        let mut words = vec![0; ENTRIES_OFFSET / 4 + entries_words + 1];
+        core.read_32(self.address, &mut words)?;
+        let (end, start) = (words[0], words[words.len() - 1]);
+        if end != start {
+            // Being modified, try again next time
+            return Ok(Vec::new());
+        }
+
+        let mut metrics = Vec::new();
+        let entries = words[ENTRIES_OFFSET / 4..][..entries_words].chunks_exact(ENTRY_WORDS);
+        for (i, entry) in entries.enumerate() {
+            let &[ty_lo, ty_hi, name_len, index, name_lo, name_hi, ..] = entry else {
+                unreachable!()
+            };
+            let name_address = name_lo as u64 | (name_hi as u64) << 32;
+            if name_address == 0 {
+                continue;
+            }
+
+            let mut name = vec![0; name_len as usize];
+            core.read(name_address, &mut name)?;
+            let name = format!("{}.{index}", String::from_utf8_lossy(&name));
+
+            let ty = [ty_lo.to_le_bytes(), ty_hi.to_le_bytes()].concat();
+            let ty = String::from_utf8_lossy(&ty);
+            let Ok(ty) = Type::try_from(ty.trim_end_matches('\0')) else {
+                println!("Unknown type {ty} of {name} in registry {}", self.name);
+                continue;
+            };
+
+            let address =
+                self.address + (ENTRIES_OFFSET + i * ENTRY_WORDS * 4) as u64 + VALUE_OFFSET;
+
+            // Keep metrics which are still there to not lose their last value
+            let existing = self.metrics.iter().position(|m| {
+                m.name == name
+                    && m.ty == ty
+                    && matches!(m.address, Address::Storage(a, _) if a == address)
+            });
+            let metric = match existing {
+                Some(i) => self.metrics.swap_remove(i),
+                None => {
+                    let math_ctx_variable_name = name.replace('.', "__");
+                    Metric {
+                        expr: ShuntingParser::parse_str(&math_ctx_variable_name).ok(),
+                        name,
+                        math_ctx_variable_name,
+                        ty,
+                        variants: None,
+                        address: Address::Storage(address, self.layout),
+                        last_value: f64::NAN,
+                        is_set: false,
+                        meta: Metadata::default(),
+                    }
+                }
+            };
+            metrics.push(metric);
+        }
+
+        let removed = std::mem::replace(&mut self.metrics, metrics)
+            .into_iter()
+            .map(|m| m.name)
+            .filter(|name| !self.metrics.iter().any(|m| &m.name == name))
+            .collect();
+        self.generation = Some(end);
+
+        Ok(removed)
+    }
+}
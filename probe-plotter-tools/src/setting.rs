@@ -1,56 +1,224 @@
 use std::ops::RangeInclusive;
 
-use probe_rs::MemoryInterface;
+use probe_plotter_common::symbol::{Bits, Metadata};
 
-use crate::{Type, read_value};
+use crate::{
+    Layout, Type, Variants, action::Action, memory::Memory, read_bits, read_storage, read_value,
+    write_bits, write_storage, write_value,
+};
+
+/// Whether the target has handled the last value written by the host
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WriteStatus {
+    /// Written but not yet seen by the target
+    Pending,
+    Applied,
+    Rejected,
+}
+
+/// Anything shown in the settings panel
+#[derive(Clone, Debug)]
+pub enum Control {
+    Setting(Setting),
+    Action(Action),
+}
+
+impl Control {
+    pub fn name(&self) -> &str {
+        match self {
+            Control::Setting(s) => &s.name,
+            Control::Action(a) => &a.name,
+        }
+    }
+
+    pub fn read(&mut self, core: &mut impl Memory) -> Result<(), probe_rs::Error> {
+        match self {
+            Control::Setting(s) => s.read(core),
+            Control::Action(a) => a.read(core),
+        }
+    }
+
+    /// Write the value of `update`, or press the button if this is an action
+    pub fn apply(
+        &mut self,
+        update: &Control,
+        core: &mut impl Memory,
+    ) -> Result<(), probe_rs::Error> {
+        match (self, update) {
+            (Control::Setting(s), Control::Setting(update)) => s.write(update.value, core),
+            (Control::Action(a), Control::Action(_)) => a.trigger(core),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns `true` if the target just handled the last write or press
+    pub fn poll_status(&mut self, core: &mut impl Memory) -> Result<bool, probe_rs::Error> {
+        match self {
+            Control::Setting(s) => s.poll_status(core),
+            Control::Action(a) => a.poll_status(core),
+        }
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct Setting {
     pub name: String,
     pub ty: Type,
+
+    /// Set if the setting is an enum
+    pub variants: Option<Variants>,
     pub address: u64,
+    pub layout: Layout,
+
+    /// Set for `make_setting_from_address!`, where the value is read and written as is rather than
+    /// as `probe_plotter::setting::SettingStorage`
+    pub hardcoded: bool,
+
+    /// Only these bits of the value at a hardcoded address belong to the setting
+    pub bits: Option<Bits>,
+
+    /// Unit and description shown in the settings panel
+    pub meta: Metadata,
+
+    /// Requested value while pending, otherwise the value applied by the target
     pub value: f64,
     pub range: RangeInclusive<f64>,
     pub step_size: f64,
+    pub status: WriteStatus,
+
+    /// Generation of the last write
+    generation: u32,
 }
 
 impl Setting {
-    pub fn read(&mut self, core: &mut probe_rs::Core) -> Result<(), probe_rs::Error> {
-        self.value = read_value(core, self.address, self.ty)?;
+    pub fn new(
+        name: String,
+        ty: Type,
+        variants: Option<Variants>,
+        address: u64,
+        layout: Layout,
+        range: RangeInclusive<f64>,
+        step_size: f64,
+    ) -> Self {
+        Setting {
+            name,
+            ty,
+            variants,
+            address,
+            layout,
+            hardcoded: false,
+            bits: None,
+            meta: Metadata::default(),
+            value: f64::NAN,
+            range,
+            step_size,
+            status: WriteStatus::Applied,
+            generation: 0,
+        }
+    }
+
+    /// Read the value currently applied by the target
+    pub fn read(&mut self, core: &mut impl Memory) -> Result<(), probe_rs::Error> {
+        if self.hardcoded {
+            self.value = match self.bits {
+                Some(bits) => read_bits(core, self.address, self.ty, bits)? as f64,
+                None => read_value(core, self.address, self.ty)?.unwrap_or(f64::NAN),
+            };
+            return Ok(());
+        }
+
+        // We are the only writer, so this will only fail if the target is still initializing the value
+        self.value = read_storage(core, self.address, self.ty, self.layout)?.unwrap_or(f64::NAN);
+        self.generation = core.read_word_32(self.address + self.generation_offset())?;
         Ok(())
     }
 
-    pub fn write(&mut self, x: f64, core: &mut probe_rs::Core) -> Result<(), probe_rs::Error> {
-        match self.ty {
-            Type::u8 => core.write_word_8(
-                self.address,
-                x.round().clamp(u8::MIN as _, u8::MAX as _) as u8,
-            )?,
-            Type::u16 => core.write_word_16(
-                self.address,
-                x.round().clamp(u16::MIN as _, u16::MAX as _) as u16,
-            )?,
-            Type::u32 => core.write_word_32(
-                self.address,
-                x.round().clamp(u32::MIN as _, u32::MAX as _) as u32,
-            )?,
-
-            Type::i8 => core.write_word_8(
-                self.address,
-                x.round().clamp(i8::MIN as _, i8::MAX as _) as u8,
-            )?,
-            Type::i16 => core.write_word_16(
-                self.address,
-                x.round().clamp(i16::MIN as _, i16::MAX as _) as u16,
-            )?,
-            Type::i32 => core.write_word_32(
-                self.address,
-                x.round().clamp(i32::MIN as _, i32::MAX as _) as u32,
-            )?,
-
-            Type::f32 => core.write_word_32(self.address, (x as f32).to_bits())?,
-        };
+    /// Request a new value, the target then either applies or rejects it, see [Setting::poll_status]
+    pub fn write(&mut self, x: f64, core: &mut impl Memory) -> Result<(), probe_rs::Error> {
+        if self.hardcoded {
+            // Takes effect right away, reported as applied by the next `poll_status`
+            match self.bits {
+                Some(bits) => write_bits(core, self.address, self.ty, bits, to_bits(x, self.ty))?,
+                None => write_value(core, self.address, self.ty, to_bits(x, self.ty))?,
+            }
+            self.value = x;
+            self.status = WriteStatus::Pending;
+            return Ok(());
+        }
+
+        let pending_address = self.address + self.layout.storage_size(self.ty);
+        let bits = to_bits(x, self.ty);
+        write_storage(core, pending_address, self.ty, bits, self.layout)?;
+
+        let generation_address = self.address + self.generation_offset();
+        let generation = core.read_word_32(generation_address)?.wrapping_add(1);
+        core.write_word_32(generation_address, generation)?;
 
+        self.value = x;
+        self.generation = generation;
+        self.status = WriteStatus::Pending;
         Ok(())
     }
+
+    /// Check whether the target has handled the last write, returns `true` if it just did
+    ///
+    /// The value is updated to what the target applied, which may differ from what was requested.
+    pub fn poll_status(&mut self, core: &mut impl Memory) -> Result<bool, probe_rs::Error> {
+        if self.status != WriteStatus::Pending {
+            return Ok(false);
+        }
+
+        if self.hardcoded {
+            // Show what the register actually holds, some bits may be read-only
+            self.status = WriteStatus::Applied;
+            self.read(core)?;
+            return Ok(true);
+        }
+
+        let generation_address = self.address + self.generation_offset();
+        if core.read_word_32(generation_address + 4)? != self.generation {
+            return Ok(false);
+        }
+
+        self.status = if core.read_word_8(generation_address + 8)? != 0 {
+            WriteStatus::Rejected
+        } else {
+            WriteStatus::Applied
+        };
+        self.read(core)?;
+        Ok(true)
+    }
+
+    /// Offset of `generation` within `probe_plotter::setting::SettingStorage`, which follows the
+    /// applied and the pending value
+    fn generation_offset(&self) -> u64 {
+        (2 * self.layout.storage_size(self.ty)).next_multiple_of(4)
+    }
+}
+
+/// Bits to write, truncated to the size of the type by `write_storage`
+fn to_bits(x: f64, ty: Type) -> u64 {
+    match ty {
+        Type::u8 => x.round().clamp(u8::MIN as _, u8::MAX as _) as u64,
+        Type::u16 => x.round().clamp(u16::MIN as _, u16::MAX as _) as u64,
+        Type::u32 => x.round().clamp(u32::MIN as _, u32::MAX as _) as u64,
+        Type::u64 => x.round().clamp(u64::MIN as _, u64::MAX as _) as u64,
+
+        Type::i8 => x.round().clamp(i8::MIN as _, i8::MAX as _) as i8 as u64,
+        Type::i16 => x.round().clamp(i16::MIN as _, i16::MAX as _) as i16 as u64,
+        Type::i32 => x.round().clamp(i32::MIN as _, i32::MAX as _) as i32 as u64,
+        Type::i64 => x.round().clamp(i64::MIN as _, i64::MAX as _) as i64 as u64,
+
+        Type::f32 => (x as f32).to_bits() as u64,
+        Type::f64 => x.to_bits(),
+
+        // Only ever write 0 or 1 since anything else is not a valid bool
+        Type::bool => (x != 0.0) as u64,
+
+        // Scale to the raw integer, then round and clamp as that integer type
+        Type::Q { frac, .. } => to_bits(
+            x * 2f64.powi(frac.into()),
+            ty.fixed_point_storage().unwrap(),
+        ),
+    }
 }
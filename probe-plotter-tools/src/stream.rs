@@ -0,0 +1,485 @@
+use std::collections::HashSet;
+
+use probe_rs::{
+    Core,
+    rtt::{self, Rtt},
+};
+use rerun::TextLogLevel;
+use shunting::MathContext;
+
+use crate::{Address, Layout, memory::Memory, metric::Metric, raw_size, raw_value_from_bytes};
+
+/// Name of the RTT control block, `probe_plotter::stream::CONTROL_BLOCK`
+pub const SYMBOL: &str = "probe_plotter.stream";
+
+/// Name of the `u32` with the number of samples which did not fit into the channel
+pub const DROPPED_SYMBOL: &str = "probe_plotter.stream_dropped";
+
+/// Name of the up channel with the samples, which is not a defmt channel
+pub const CHANNEL_NAME: &str = "probe-plotter";
+
+/// Name of the `u32` with the ticks per second of the timestamps, defined by
+/// `probe_plotter::timestamp!`
+pub const FREQUENCY_SYMBOL: &str = "probe_plotter.timestamp_frequency";
+
+/// Offset of the up channel within the RTT control block, after the id and the number of channels
+const CHANNEL_OFFSET: u64 = 24;
+
+/// Timeline of the samples, the time since the target started in seconds
+pub const TIMELINE: &str = "target_time";
+
+/// Up channel with the samples of a [Stream]
+pub trait Channel<M> {
+    /// Read as many bytes as fit into `buf`, returns the number of bytes read
+    fn read(&mut self, memory: &mut M, buf: &mut [u8]) -> Result<usize, probe_rs::Error>;
+}
+
+impl<'a> Channel<Core<'a>> for rtt::UpChannel {
+    fn read(&mut self, core: &mut Core<'a>, buf: &mut [u8]) -> Result<usize, probe_rs::Error> {
+        rtt::UpChannel::read(self, core, buf).map_err(|e| probe_rs::Error::Other(e.to_string()))
+    }
+}
+
+/// Up channel read from memory as is, for [crate::local] where there is no probe to attach with
+///
+/// The fields of the channel are pointer sized, see `SEGGER_RTT_BUFFER_UP`.
+#[derive(Debug)]
+pub struct MemoryChannel {
+    address: u64,
+    layout: Layout,
+}
+
+impl MemoryChannel {
+    fn read_field<M: Memory>(&self, memory: &mut M, index: u64) -> Result<u64, probe_rs::Error> {
+        let address = self.address + index * self.layout.pointer_size();
+        Ok(match self.layout {
+            Layout::Bits32 => memory.read_word_32(address)? as u64,
+            Layout::Bits64 => memory.read_word_64(address)?,
+        })
+    }
+}
+
+impl<M: Memory> Channel<M> for MemoryChannel {
+    fn read(&mut self, memory: &mut M, buf: &mut [u8]) -> Result<usize, probe_rs::Error> {
+        let buffer = self.read_field(memory, 1)?;
+        let size = self.read_field(memory, 2)?;
+        let write = self.read_field(memory, 3)?;
+        let read = self.read_field(memory, 4)?;
+        if write >= size || read >= size {
+            return Ok(0);
+        }
+
+        // Up to the end of the buffer, the rest is read the next time
+        let end = if write < read { size } else { write };
+        let len = ((end - read) as usize).min(buf.len());
+        memory.read(buffer + read, &mut buf[..len])?;
+
+        let read = (read + len as u64) % size;
+        let address = self.address + 4 * self.layout.pointer_size();
+        match self.layout {
+            Layout::Bits32 => memory.write_word_32(address, read as u32)?,
+            Layout::Bits64 => memory.write_word_64(address, read)?,
+        }
+        Ok(len)
+    }
+}
+
+/// Samples sent by the target with `Metric::push`
+///
+/// The samples are sent through an RTT up channel with a control block of its own, laid out as
+/// `probe_plotter::stream::ControlBlock`. Every update the host reads all new samples, which
+/// makes room for more.
+#[derive(Debug)]
+pub struct Stream {
+    /// Address of the RTT control block
+    pub address: u64,
+    pub dropped_address: u64,
+    pub layout: Layout,
+
+    /// Ticks per second of the timestamps
+    pub frequency: u64,
+
+    /// Timestamp of the last sample, the target only sends the lower 32 bits
+    timestamp: u64,
+
+    /// Value of the dropped counter the last time it was read
+    dropped: u32,
+
+    /// Received bytes of a sample which has not been completely received yet
+    pending: Vec<u8>,
+
+    /// Storage addresses of metrics which have been pushed, these are no longer read as well
+    streamed: HashSet<u64>,
+}
+
+impl Stream {
+    pub fn new(address: u64, dropped_address: u64, layout: Layout, frequency: u64) -> Self {
+        Stream {
+            address,
+            dropped_address,
+            layout,
+            frequency,
+            timestamp: 0,
+            dropped: 0,
+            pending: Vec::new(),
+            streamed: HashSet::new(),
+        }
+    }
+
+    /// Attach to the control block of the stream through the probe, next to the one of defmt
+    pub fn attach(&self, core: &mut Core) -> Result<rtt::UpChannel, probe_rs::Error> {
+        let rtt = Rtt::attach_region(core, &rtt::ScanRegion::Exact(self.address))
+            .map_err(|e| probe_rs::Error::Other(e.to_string()))?;
+        rtt.up_channels
+            .into_iter()
+            .find(|ch| ch.name() == Some(CHANNEL_NAME))
+            .ok_or_else(|| probe_rs::Error::Other(format!("Found no {CHANNEL_NAME} channel")))
+    }
+
+    /// The up channel of the stream read from memory as is, see [MemoryChannel]
+    pub fn memory_channel(&self) -> MemoryChannel {
+        MemoryChannel {
+            address: self.address + CHANNEL_OFFSET,
+            layout: self.layout,
+        }
+    }
+
+    /// Whether samples of the metric have been received, the metric is then only logged from the
+    /// samples
+    pub fn is_streamed(&self, metric: &Metric) -> bool {
+        matches!(metric.address, Address::Storage(a, _) if self.streamed.contains(&a))
+    }
+
+    /// Read every sample sent since the last time and log them at their timestamps
+    pub fn receive<M: Memory>(
+        &mut self,
+        memory: &mut M,
+        channel: &mut impl Channel<M>,
+        rec: &rerun::RecordingStream,
+        metrics: &mut [Metric],
+        math_ctx: &mut MathContext,
+    ) -> Result<(), probe_rs::Error> {
+        let dropped = memory.read_word_32(self.dropped_address)?;
+        if dropped != self.dropped {
+            let message = format!(
+                "Dropped {} samples, increase PROBE_PLOTTER_STREAM_SIZE or the update rate",
+                dropped.wrapping_sub(self.dropped)
+            );
+            rec.log(
+                "log",
+                &rerun::TextLog::new(message).with_level(TextLogLevel::WARN),
+            )
+            .unwrap();
+            self.dropped = dropped;
+        }
+
+        let mut buf = [0; 1024];
+        loop {
+            let len = channel.read(memory, &mut buf)?;
+            if len == 0 {
+                break;
+            }
+            self.pending.extend_from_slice(&buf[..len]);
+        }
+
+        let data = std::mem::take(&mut self.pending);
+        let mut samples = data.as_slice();
+        while let Some((header, rest)) = samples.split_first_chunk::<8>() {
+            let timestamp = u32::from_le_bytes(header[..4].try_into().unwrap());
+            let id = u32::from_le_bytes(header[4..].try_into().unwrap());
+
+            // The id is the address of the storage of the metric, truncated to 32 bits
+            let Some(m) = metrics
+                .iter_mut()
+                .find(|m| matches!(m.address, Address::Storage(a, _) if a as u32 == id))
+            else {
+                // The size of the sample is not known, so the rest can not be decoded either
+                println!("Received a sample of an unknown metric {id:#x}, skipping the rest");
+                samples = &[];
+                break;
+            };
+            // 64 bit values are sent as is rather than as `probe_plotter::metric::Wide`
+            let Some((value, rest)) = rest.split_at_checked(raw_size(m.ty) as usize) else {
+                break;
+            };
+            samples = rest;
+
+            if let Address::Storage(a, _) = m.address {
+                self.streamed.insert(a);
+            }
+
+            // Extend the timestamp assuming it wrapped around at most once since the last sample
+            self.timestamp += timestamp.wrapping_sub(self.timestamp as u32) as u64;
+
+            let x = raw_value_from_bytes(value, m.ty);
+            math_ctx.setvar(&m.math_ctx_variable_name, shunting::MathOp::Number(x));
+            m.is_set = true;
+
+            let previous = m.last_value;
+            if let Some((x, _s)) = m.compute(math_ctx) {
+                let seconds = self.timestamp as f64 / self.frequency as f64;
+                rec.set_time(
+                    TIMELINE,
+                    rerun::TimeCell::from_duration_nanos((seconds * 1e9) as i64),
+                );
+                m.log(rec, x, previous);
+            }
+        }
+
+        // The rest of the last sample is received the next time
+        self.pending = samples.to_vec();
+
+        // Values read from memory have no timestamp of the target
+        rec.disable_timeline(TIMELINE);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use probe_plotter_common::symbol::Metadata;
+    use shunting::ShuntingParser;
+
+    use super::*;
+    use crate::Type;
+
+    /// Where the parts of the stream are placed in [FakeMemory]
+    const CONTROL_BLOCK: u64 = 0x00;
+    const DROPPED: u64 = 0x40;
+    const BUFFER: u64 = 0x100;
+    const BUFFER_SIZE: u64 = 32;
+    const CHANNEL: u64 = CONTROL_BLOCK + CHANNEL_OFFSET;
+
+    /// Storage of the metric the samples are sent for, which is also its id
+    const STORAGE: u64 = 0x2000;
+
+    /// Memory of a 32 bit target, starting at address 0
+    struct FakeMemory(Vec<u8>);
+
+    impl FakeMemory {
+        fn new() -> Self {
+            let mut memory = FakeMemory(vec![0; 0x200]);
+            memory.write_word_32(CHANNEL + 4, BUFFER as u32).unwrap();
+            memory
+                .write_word_32(CHANNEL + 8, BUFFER_SIZE as u32)
+                .unwrap();
+            memory
+        }
+
+        fn bytes(&mut self, address: u64, len: usize) -> &mut [u8] {
+            &mut self.0[address as usize..][..len]
+        }
+
+        fn write_index(&mut self) -> u64 {
+            self.read_word_32(CHANNEL + 12).unwrap() as u64
+        }
+
+        fn read_index(&mut self) -> u64 {
+            self.read_word_32(CHANNEL + 16).unwrap() as u64
+        }
+
+        /// Start both the write and read index at `index`, as if samples up to there have been
+        /// sent and received
+        fn start_at(&mut self, index: u64) {
+            self.write_word_32(CHANNEL + 12, index as u32).unwrap();
+            self.write_word_32(CHANNEL + 16, index as u32).unwrap();
+        }
+
+        /// Write `data` into the buffer the way the target does, wrapping around at its end
+        fn send(&mut self, data: &[u8]) {
+            let mut write = self.write_index();
+            for byte in data {
+                self.0[(BUFFER + write) as usize] = *byte;
+                write = (write + 1) % BUFFER_SIZE;
+            }
+            self.write_word_32(CHANNEL + 12, write as u32).unwrap();
+        }
+    }
+
+    impl Memory for FakeMemory {
+        fn read_word_8(&mut self, address: u64) -> Result<u8, probe_rs::Error> {
+            Ok(self.bytes(address, 1)[0])
+        }
+
+        fn read_word_16(&mut self, address: u64) -> Result<u16, probe_rs::Error> {
+            Ok(u16::from_le_bytes(
+                self.bytes(address, 2).try_into().unwrap(),
+            ))
+        }
+
+        fn read_word_32(&mut self, address: u64) -> Result<u32, probe_rs::Error> {
+            Ok(u32::from_le_bytes(
+                self.bytes(address, 4).try_into().unwrap(),
+            ))
+        }
+
+        fn read_word_64(&mut self, address: u64) -> Result<u64, probe_rs::Error> {
+            Ok(u64::from_le_bytes(
+                self.bytes(address, 8).try_into().unwrap(),
+            ))
+        }
+
+        fn read_32(&mut self, address: u64, data: &mut [u32]) -> Result<(), probe_rs::Error> {
+            for (i, word) in data.iter_mut().enumerate() {
+                *word = self.read_word_32(address + 4 * i as u64)?;
+            }
+            Ok(())
+        }
+
+        fn read(&mut self, address: u64, data: &mut [u8]) -> Result<(), probe_rs::Error> {
+            data.copy_from_slice(self.bytes(address, data.len()));
+            Ok(())
+        }
+
+        fn write_word_8(&mut self, address: u64, data: u8) -> Result<(), probe_rs::Error> {
+            self.bytes(address, 1)[0] = data;
+            Ok(())
+        }
+
+        fn write_word_16(&mut self, address: u64, data: u16) -> Result<(), probe_rs::Error> {
+            self.bytes(address, 2).copy_from_slice(&data.to_le_bytes());
+            Ok(())
+        }
+
+        fn write_word_32(&mut self, address: u64, data: u32) -> Result<(), probe_rs::Error> {
+            self.bytes(address, 4).copy_from_slice(&data.to_le_bytes());
+            Ok(())
+        }
+
+        fn write_word_64(&mut self, address: u64, data: u64) -> Result<(), probe_rs::Error> {
+            self.bytes(address, 8).copy_from_slice(&data.to_le_bytes());
+            Ok(())
+        }
+
+        fn write_32(&mut self, address: u64, data: &[u32]) -> Result<(), probe_rs::Error> {
+            for (i, word) in data.iter().enumerate() {
+                self.write_word_32(address + 4 * i as u64, *word)?;
+            }
+            Ok(())
+        }
+
+        fn is_ram(&mut self, address: u64, len: u64) -> bool {
+            address + len <= self.0.len() as u64
+        }
+    }
+
+    fn metric() -> Metric {
+        Metric {
+            name: "FOO".to_owned(),
+            math_ctx_variable_name: "FOO".to_owned(),
+            expr: Some(ShuntingParser::parse_str("FOO").unwrap()),
+            ty: Type::u32,
+            variants: None,
+            address: Address::Storage(STORAGE, Layout::Bits32),
+            last_value: f64::NAN,
+            is_set: false,
+            meta: Metadata::default(),
+        }
+    }
+
+    /// A sample of [metric], as sent by `Metric::push`
+    fn sample(timestamp: u32, id: u32, value: u32) -> Vec<u8> {
+        [timestamp, id, value]
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .collect()
+    }
+
+    struct Fixture {
+        memory: FakeMemory,
+        stream: Stream,
+        channel: MemoryChannel,
+        metrics: Vec<Metric>,
+        math_ctx: MathContext,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let stream = Stream::new(CONTROL_BLOCK, DROPPED, Layout::Bits32, 1000);
+            let channel = stream.memory_channel();
+            Fixture {
+                memory: FakeMemory::new(),
+                stream,
+                channel,
+                metrics: vec![metric()],
+                math_ctx: MathContext::new(),
+            }
+        }
+
+        fn receive(&mut self) {
+            self.stream
+                .receive(
+                    &mut self.memory,
+                    &mut self.channel,
+                    &rerun::RecordingStream::disabled(),
+                    &mut self.metrics,
+                    &mut self.math_ctx,
+                )
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn sample_wrapping_around_the_buffer_is_received() {
+        let mut f = Fixture::new();
+        f.memory.start_at(BUFFER_SIZE - 4);
+        f.memory.send(&sample(1, STORAGE as u32, 42));
+        assert!(f.memory.write_index() < f.memory.read_index());
+
+        f.receive();
+
+        assert_eq!(f.metrics[0].last_value, 42.0);
+        assert_eq!(f.memory.read_index(), f.memory.write_index());
+        assert!(f.stream.pending.is_empty());
+        assert!(f.stream.is_streamed(&f.metrics[0]));
+    }
+
+    #[test]
+    fn sample_split_across_the_buffer_end_is_kept_until_complete() {
+        let mut f = Fixture::new();
+        f.memory.start_at(BUFFER_SIZE - 8);
+        let sample = sample(1, STORAGE as u32, 42);
+
+        // Only the header has been written when the host reads
+        f.memory.send(&sample[..8]);
+        f.receive();
+        assert!(!f.metrics[0].is_set);
+        assert_eq!(f.stream.pending, sample[..8]);
+
+        f.memory.send(&sample[8..]);
+        f.receive();
+        assert_eq!(f.metrics[0].last_value, 42.0);
+        assert!(f.stream.pending.is_empty());
+    }
+
+    #[test]
+    fn timestamp_is_extended_past_32_bits() {
+        let mut f = Fixture::new();
+        f.memory.send(&sample(0xffff_fff0, STORAGE as u32, 1));
+        f.receive();
+        assert_eq!(f.stream.timestamp, 0xffff_fff0);
+
+        f.memory.send(&sample(0x10, STORAGE as u32, 2));
+        f.receive();
+        assert_eq!(f.stream.timestamp, 0x1_0000_0010);
+    }
+
+    #[test]
+    fn unknown_id_discards_the_rest() {
+        let mut f = Fixture::new();
+        f.memory.send(&sample(1, 0x1234, 7));
+        f.memory.send(&sample(2, STORAGE as u32, 42));
+
+        f.receive();
+
+        assert!(!f.metrics[0].is_set);
+        assert!(f.stream.pending.is_empty());
+        assert_eq!(f.memory.read_index(), f.memory.write_index());
+
+        // Samples sent after that are received again
+        f.memory.send(&sample(3, STORAGE as u32, 42));
+        f.receive();
+        assert_eq!(f.metrics[0].last_value, 42.0);
+    }
+}
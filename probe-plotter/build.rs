@@ -0,0 +1,23 @@
+use std::{env, fs, path::PathBuf};
+
+fn main() {
+    // Make `-Tprobe-plotter.x` available to the linker, same as `defmt.x` and `link.x`
+    let out = PathBuf::from(env::var("OUT_DIR").unwrap());
+    fs::copy("probe-plotter.x", out.join("probe-plotter.x")).unwrap();
+    println!("cargo:rustc-link-search={}", out.display());
+    println!("cargo:rerun-if-changed=probe-plotter.x");
+
+    // Size of the RTT channel of `Metric::push`, see `stream`
+    let stream_size: usize = env::var("PROBE_PLOTTER_STREAM_SIZE")
+        .map(|size| {
+            size.parse()
+                .expect("PROBE_PLOTTER_STREAM_SIZE must be a number of bytes")
+        })
+        .unwrap_or(1024);
+    fs::write(
+        out.join("stream_size.rs"),
+        format!("const STREAM_SIZE: usize = {stream_size};\n"),
+    )
+    .unwrap();
+    println!("cargo:rerun-if-env-changed=PROBE_PLOTTER_STREAM_SIZE");
+}
@@ -0,0 +1,8 @@
+/* Metadata read by probe-plotter-tools from the elf file, it is not loaded onto the target */
+SECTIONS
+{
+  .probe_plotter (INFO) :
+  {
+    KEEP(*(.probe_plotter));
+  }
+}
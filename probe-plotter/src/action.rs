@@ -0,0 +1,69 @@
+pub use macros::make_action;
+
+/// Memory of an [Action], shared with the host
+///
+/// The host increments `presses` every time the button is pressed. The target increments `taken`
+/// once for every press it has handled, which lets the host know when the press has been seen.
+///
+/// Read by the host, so the layout must not change without also changing probe-plotter-tools.
+#[repr(C)]
+pub struct ActionStorage {
+    presses: u32,
+    taken: u32,
+}
+
+impl ActionStorage {
+    pub const ZERO: Self = ActionStorage {
+        presses: 0,
+        taken: 0,
+    };
+}
+
+pub struct Action {
+    storage: *mut ActionStorage,
+}
+
+// Safety: No one besides us and the debug probe has the raw pointer, so we can safely transfer
+// Action to another thread / execution context.
+unsafe impl Send for Action {}
+
+// Safety: We only allow mutability through exclusive references so there is no risk
+// in having multiple shared references to this value across threads/execution contexts
+unsafe impl Sync for Action {}
+
+/// Create using [make_action]
+///
+/// ```
+/// let mut clear_fault = probe_plotter::make_action!(CLEAR_FAULT).unwrap();
+/// loop {
+///     if clear_fault.take() {
+///         // Clear the fault
+///     }
+///     # break;
+/// }
+/// ```
+///
+/// Will create an action which will show as a button on the host side.
+impl Action {
+    /// # Safety
+    /// Internal use only by [make_action]
+    pub const unsafe fn new(storage: *mut ActionStorage) -> Self {
+        Action { storage }
+    }
+
+    /// Returns `true` once for every time the button has been pressed
+    ///
+    /// Presses made since the last call are not lost, every one of them results in one call
+    /// returning `true`.
+    pub fn take(&mut self) -> bool {
+        unsafe {
+            let presses = (&raw const (*self.storage).presses).read_volatile();
+            let taken = (&raw const (*self.storage).taken).read_volatile();
+            if presses == taken {
+                return false;
+            }
+            (&raw mut (*self.storage).taken).write_volatile(taken.wrapping_add(1));
+            true
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use crate::metric::{LoadError, Metricable};
+
+/// Signed fixed point number stored as `T` with `FRAC` fractional bits
+///
+/// The value is `bits / 2^FRAC`. The host converts to and from real values, so plots and settings
+/// sliders show the actual value rather than the raw integer.
+///
+/// In the macros these are written as `Q15` for Q0.15 or `Q3_12` for Q3.12, which the macro
+/// will map to `Fixed<i16, 15>` and `Fixed<i16, 12>` respectively.
+///
+/// ```
+/// use probe_plotter::fixed::{Fixed, Q15};
+///
+/// let mut gain = probe_plotter::make_setting!(GAIN: Q15 = Q15::from_f32(0.5), -1.0..=0.99, 0.01).unwrap();
+/// let mut out = probe_plotter::make_metric!(OUT: Q3_12 = Fixed(0)).unwrap();
+///
+/// let raw: i16 = gain.get().to_bits();
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(transparent)]
+pub struct Fixed<T, const FRAC: u32>(pub T);
+
+pub type Q7 = Fixed<i8, 7>;
+pub type Q15 = Fixed<i16, 15>;
+pub type Q31 = Fixed<i32, 31>;
+
+macro_rules! impl_fixed {
+    ($($t:ty),*) => {$(
+        impl<const FRAC: u32> Fixed<$t, FRAC> {
+            pub const fn from_bits(bits: $t) -> Self {
+                Fixed(bits)
+            }
+
+            pub const fn to_bits(self) -> $t {
+                self.0
+            }
+
+            /// Rounds to the nearest representable value, saturates values outside of the range
+            pub fn from_f32(x: f32) -> Self {
+                let scaled = x * (1u64 << FRAC) as f32;
+                let rounded = if scaled < 0.0 { scaled - 0.5 } else { scaled + 0.5 };
+                Fixed(rounded as $t)
+            }
+
+            pub fn to_f32(self) -> f32 {
+                self.0 as f32 / (1u64 << FRAC) as f32
+            }
+        }
+
+        impl<const FRAC: u32> Metricable for Fixed<$t, FRAC> {
+            type Storage = $t;
+            const ZERO: $t = 0;
+
+            unsafe fn store(storage: *mut $t, x: Self) {
+                unsafe { storage.write_volatile(x.0) }
+            }
+
+            unsafe fn load(storage: *const $t) -> Result<Self, LoadError> {
+                Ok(Fixed(unsafe { storage.read_volatile() }))
+            }
+        }
+    )*};
+}
+
+impl_fixed!(i8, i16, i32);
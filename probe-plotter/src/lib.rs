@@ -1,10 +1,37 @@
-#![no_std]
+#![cfg_attr(not(feature = "std"), no_std)]
 
+pub mod action;
+pub mod fixed;
 pub mod metric;
+pub mod metric_array;
+pub mod metric_group;
+#[cfg(any(feature = "cortex-m", feature = "critical-section", feature = "std"))]
+pub mod registry;
 pub mod setting;
+#[cfg(any(feature = "cortex-m", feature = "critical-section", feature = "std"))]
+pub mod stream;
+pub mod sync;
+mod timestamp;
 
+pub use action::{Action, make_action};
 pub use macros::make_metric_from_address;
 pub use macros::make_metric_from_base_with_offset;
+pub use macros::make_metric_from_symbol;
 pub use macros::make_ptr;
-pub use metric::{Metric, make_metric};
-pub use setting::{Setting, make_setting};
+pub use metric::{Metric, Metricable, Plottable, make_metric, make_metric_array_family};
+pub use metric_array::{MetricArray, make_metric_array};
+pub use metric_group::{MetricGroup, make_metric_group};
+#[cfg(any(feature = "cortex-m", feature = "critical-section", feature = "std"))]
+pub use registry::{DynamicMetric, Registry, make_registry};
+#[cfg(feature = "async")]
+pub use setting::poll_settings;
+pub use setting::{Setting, make_setting, make_setting_from_address};
+pub use timestamp::timestamp;
+
+/// Version of the metadata format written by the macros, checked by probe-plotter-tools before
+/// reading the metadata
+///
+/// Taken from the macros, so it is always the version of the records they write
+#[used]
+#[unsafe(export_name = "probe_plotter.version")]
+static VERSION: u32 = macros::metadata_version!();
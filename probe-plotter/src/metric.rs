@@ -1,18 +1,176 @@
-// TODO: Adjust size constraints for targets other than 32bit
+use core::sync::atomic::{Ordering, fence};
 
-pub trait Metricable: Sized {}
-impl Metricable for i8 {}
-impl Metricable for i16 {}
-impl Metricable for i32 {}
-impl Metricable for u8 {}
-impl Metricable for u16 {}
-impl Metricable for u32 {}
-impl Metricable for f32 {}
+/// Types which can be used as [Metric]s and [crate::Setting]s
+pub trait Metricable: Sized + Copy {
+    /// How the value is laid out in the memory which is read by the host
+    type Storage: Copy;
 
-pub use macros::make_metric;
+    /// Value of the storage before it is initialized
+    const ZERO: Self::Storage;
+
+    /// # Safety
+    /// `storage` must be valid for writes
+    unsafe fn store(storage: *mut Self::Storage, x: Self);
+
+    /// # Safety
+    /// `storage` must be valid for reads
+    unsafe fn load(storage: *const Self::Storage) -> Result<Self, LoadError>;
+}
+
+/// Why [Metricable::load] did not return a value
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoadError {
+    /// The value was being written while reading, reading again will succeed
+    Torn,
+    /// The storage does not hold a valid value of the type, such as an unknown enum discriminant
+    /// written by the host
+    Invalid,
+}
+
+macro_rules! impl_metricable {
+    ($($t:ty = $zero:expr),*) => {$(
+        impl Metricable for $t {
+            type Storage = $t;
+            const ZERO: $t = $zero;
+
+            unsafe fn store(storage: *mut $t, x: $t) {
+                // Volatile since only the probe reads the storage, a plain store could be
+                // optimized out
+                unsafe { storage.write_volatile(x) }
+            }
+
+            unsafe fn load(storage: *const $t) -> Result<$t, LoadError> {
+                Ok(unsafe { storage.read_volatile() })
+            }
+        }
+    )*};
+}
+
+impl_metricable!(
+    i8 = 0,
+    i16 = 0,
+    i32 = 0,
+    u8 = 0,
+    u16 = 0,
+    u32 = 0,
+    f32 = 0.0,
+    bool = false
+);
+
+// Pointer sized, such as the base of `make_metric_from_base_with_offset!`. The host reads it as a
+// u32 or u64 depending on whether the ELF file is 32 or 64 bit
+impl_metricable!(usize = 0);
+
+// 64 bit targets write 64 bit values in one store, so these are stored as is. The host decides
+// which layout to expect based on whether the ELF file is 32 or 64 bit.
+#[cfg(target_pointer_width = "64")]
+impl_metricable!(i64 = 0, u64 = 0, f64 = 0.0);
+
+/// Storage for values wider than the targets word size
+///
+/// A 64 bit value can not be written in one store on a 32bit target. To make sure the reader never
+/// sees a half updated value, the writer increments `start` before and sets `end` to the same value
+/// after writing the value. The reader reads `end` first and `start` last, if they differ then
+/// the value was being written in the mean time and has to be read again.
+///
+/// `end` is placed first in memory so that the host can read all of it in one sequential read.
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct Wide {
+    end: u32,
+    bits: [u32; 2],
+    start: u32,
+}
+
+impl Wide {
+    pub const ZERO: Self = Wide {
+        end: 0,
+        bits: [0; 2],
+        start: 0,
+    };
+
+    /// # Safety
+    /// `this` must be valid for writes
+    #[cfg_attr(target_pointer_width = "64", allow(dead_code))]
+    unsafe fn store(this: *mut Self, bits: u64) {
+        unsafe {
+            let seq = (&raw const (*this).start).read_volatile().wrapping_add(1);
+            (&raw mut (*this).start).write_volatile(seq);
+            fence(Ordering::Release);
+            (&raw mut (*this).bits).write_volatile([bits as u32, (bits >> 32) as u32]);
+            fence(Ordering::Release);
+            (&raw mut (*this).end).write_volatile(seq);
+        }
+    }
+
+    /// # Safety
+    /// `this` must be valid for reads
+    #[cfg_attr(target_pointer_width = "64", allow(dead_code))]
+    unsafe fn load(this: *const Self) -> Option<u64> {
+        unsafe {
+            let end = (&raw const (*this).end).read_volatile();
+            fence(Ordering::Acquire);
+            let [lo, hi] = (&raw const (*this).bits).read_volatile();
+            fence(Ordering::Acquire);
+            let start = (&raw const (*this).start).read_volatile();
+
+            (start == end).then_some(lo as u64 | (hi as u64) << 32)
+        }
+    }
+}
+
+#[cfg(not(target_pointer_width = "64"))]
+macro_rules! impl_metricable_wide {
+    ($($t:ty => |$x:ident| $to_bits:expr, |$bits:ident| $from_bits:expr;)*) => {$(
+        impl Metricable for $t {
+            type Storage = Wide;
+            const ZERO: Wide = Wide::ZERO;
+
+            unsafe fn store(storage: *mut Wide, $x: $t) {
+                unsafe { Wide::store(storage, $to_bits) }
+            }
+
+            unsafe fn load(storage: *const Wide) -> Result<$t, LoadError> {
+                unsafe { Wide::load(storage) }
+                    .map(|$bits| $from_bits)
+                    .ok_or(LoadError::Torn)
+            }
+        }
+    )*};
+}
+
+#[cfg(not(target_pointer_width = "64"))]
+impl_metricable_wide! {
+    u64 => |x| x, |bits| bits;
+    i64 => |x| x as u64, |bits| bits as i64;
+    f64 => |x| x.to_bits(), |bits| f64::from_bits(bits);
+}
+
+pub use macros::{Metricable, Plottable, make_metric, make_metric_array_family};
+
+/// Internal use only by [crate::make_metric_from_symbol]
+///
+/// Refers to the plotted static to keep the linker from removing it. The host reads the address of
+/// the static from it, so the layout must not change without also changing probe-plotter-tools.
+#[repr(transparent)]
+pub struct SymbolRef(#[allow(dead_code)] *const ());
+
+// Safety: The pointer is never dereferenced
+unsafe impl Sync for SymbolRef {}
+
+impl SymbolRef {
+    /// Fails to compile if the static is not `size` bytes large
+    pub const fn new<T>(x: *const T, size: usize) -> Self {
+        assert!(
+            size_of::<T>() == size,
+            "The size of the type does not match the size of the static"
+        );
+        SymbolRef(x.cast())
+    }
+}
 
 pub struct Metric<T: Metricable> {
-    x: *mut T,
+    x: *mut T::Storage,
 }
 
 // Safety: No one besides us and the debug probe has the raw pointer, so we can safely transfer
@@ -26,7 +184,7 @@ unsafe impl<T> Sync for Metric<T> where T: Sync + Metricable {}
 /// Create using [make_metric]
 ///
 /// ```
-/// let mut metric_foo = macros::make_metric!(FOO: i32 = 0, "x * 3.0").unwrap();
+/// let mut metric_foo = macros::make_metric!(FOO: i32 = 0, "FOO * 3.0").unwrap();
 /// metric_foo.set(42);
 /// ```
 ///
@@ -38,18 +196,30 @@ unsafe impl<T> Sync for Metric<T> where T: Sync + Metricable {}
 impl<T: Metricable> Metric<T> {
     /// # Safety
     /// Internal use only by [make_metric] macro and friends.
-    pub const unsafe fn new(x: *mut T) -> Self {
+    pub const unsafe fn new(x: *mut T::Storage) -> Self {
         Metric { x }
     }
 
     pub fn set(&mut self, x: T) {
-        unsafe {
-            // TODO: Is volatile the right thing to use here?
-            self.x.write_volatile(x);
-        }
+        unsafe { T::store(self.x, x) }
     }
 
     pub fn get(&mut self) -> T {
-        unsafe { self.x.read_volatile() }
+        // We are the only writer so the value is never torn
+        loop {
+            if let Ok(x) = unsafe { T::load(self.x) } {
+                return x;
+            }
+        }
+    }
+}
+
+#[cfg(any(feature = "cortex-m", feature = "critical-section", feature = "std"))]
+impl<T: crate::stream::Pushable> Metric<T> {
+    /// Set the value and also send it to the host with a timestamp, so every value is plotted
+    /// however fast it changes. See [crate::stream]
+    pub fn push(&mut self, x: T) {
+        self.set(x);
+        crate::stream::push(self.x as usize as u32, x);
     }
 }
@@ -0,0 +1,69 @@
+use core::sync::atomic::{Ordering, fence};
+
+pub use macros::make_metric_array;
+
+use crate::metric::Metricable;
+
+/// Storage for a [MetricArray]
+///
+/// Uses the same protocol as [crate::metric::Wide] to make sure the host never sees a half
+/// published array. The writer increments `start` before and sets `end` to the same value after
+/// writing the data.
+#[repr(C)]
+pub struct ArrayStorage<T: Metricable, const N: usize> {
+    end: u32,
+    data: [T::Storage; N],
+    start: u32,
+}
+
+impl<T: Metricable, const N: usize> ArrayStorage<T, N> {
+    pub const ZERO: Self = ArrayStorage {
+        end: 0,
+        data: [T::ZERO; N],
+        start: 0,
+    };
+}
+
+pub struct MetricArray<T: Metricable, const N: usize> {
+    x: *mut ArrayStorage<T, N>,
+}
+
+// Safety: No one besides us and the debug probe has the raw pointer, so we can safely transfer
+// MetricArray to another thread / execution context if T can be safely transferred.
+unsafe impl<T, const N: usize> Send for MetricArray<T, N> where T: Send + Metricable {}
+
+// Safety: We only allow mutability through exclusive references so there is no risk
+// in having multiple shared references to this value across threads/execution contexts
+unsafe impl<T, const N: usize> Sync for MetricArray<T, N> where T: Sync + Metricable {}
+
+/// Create using [make_metric_array]
+///
+/// ```
+/// let mut adc_buffer = probe_plotter::make_metric_array!(ADC_BUFFER: [i16; 256]).unwrap();
+/// adc_buffer.publish(&dma_buffer);
+/// ```
+///
+/// Will create an array metric which on the host side will be called `ADC_BUFFER` and it will
+/// be shown as a waveform of all 256 values, updated every time a new buffer is published.
+impl<T: Metricable, const N: usize> MetricArray<T, N> {
+    /// # Safety
+    /// Internal use only by [make_metric_array]
+    pub const unsafe fn new(x: *mut ArrayStorage<T, N>) -> Self {
+        MetricArray { x }
+    }
+
+    /// Publish a new snapshot of all values to the host
+    pub fn publish(&mut self, values: &[T; N]) {
+        unsafe {
+            let seq = (&raw const (*self.x).start).read_volatile().wrapping_add(1);
+            (&raw mut (*self.x).start).write_volatile(seq);
+            fence(Ordering::Release);
+            let data = &raw mut (*self.x).data;
+            for (i, x) in values.iter().enumerate() {
+                T::store(data.cast::<T::Storage>().add(i), *x);
+            }
+            fence(Ordering::Release);
+            (&raw mut (*self.x).end).write_volatile(seq);
+        }
+    }
+}
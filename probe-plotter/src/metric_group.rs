@@ -0,0 +1,122 @@
+use core::sync::atomic::{Ordering, fence};
+
+pub use macros::make_metric_group;
+
+use crate::metric::Metricable;
+
+/// One buffer of a [GroupStorage]
+///
+/// Padded to whole words, so the host can tell where the second buffer starts from the size of the
+/// storage.
+#[repr(C, align(4))]
+struct Buffer<S>(S);
+
+/// Storage for a [MetricGroup]
+///
+/// Read by the host, so the layout must not change without also changing probe-plotter-tools. The
+/// target sets the values in one buffer while the host reads the other one. Every commit
+/// increments `commits`, whose lowest bit is the index of the buffer with the last commit. The host
+/// reads `commits` before and after the buffer, if they differ then the buffer may have been
+/// written in the mean time and has to be read again.
+#[repr(C)]
+pub struct GroupStorage<T: Metricable> {
+    commits: u32,
+
+    /// Timestamp of the last commit of each buffer, see [crate::timestamp]
+    timestamps: [u32; 2],
+
+    /// Set once the [MetricGroup] has been handed out, not read by the host
+    taken: bool,
+
+    /// Starts 16 bytes in, see the assert in [MetricGroup::take]
+    buffers: [Buffer<T::Storage>; 2],
+}
+
+impl<T: Metricable> GroupStorage<T> {
+    pub const ZERO: Self = GroupStorage {
+        commits: 0,
+        timestamps: [0; 2],
+        taken: false,
+        buffers: [const { Buffer(T::ZERO) }; 2],
+    };
+}
+
+/// Several values which the host always reads together, as they were at the same commit
+///
+/// Every metric is otherwise read on its own, so related values such as the d and q currents of a
+/// motor may be from different iterations of the control loop. A group is double buffered, the
+/// values are set in one buffer while the host reads the other one and [MetricGroup::commit] swaps
+/// them. Every field of the struct is shown as a metric of its own, all plotted at the time of the
+/// commit. This requires [crate::timestamp].
+pub struct MetricGroup<T: Metricable> {
+    x: *mut GroupStorage<T>,
+}
+
+// Safety: No one besides us and the debug probe has the raw pointer, so we can safely transfer
+// MetricGroup to another thread / execution context if T can be safely transferred.
+unsafe impl<T> Send for MetricGroup<T> where T: Send + Metricable {}
+
+// Safety: We only allow mutability through exclusive references so there is no risk
+// in having multiple shared references to this value across threads/execution contexts
+unsafe impl<T> Sync for MetricGroup<T> where T: Sync + Metricable {}
+
+/// Create using [make_metric_group]
+///
+/// ```
+/// #[derive(Clone, Copy, probe_plotter::Plottable)]
+/// struct Currents {
+///     d: f32,
+///     q: f32,
+/// }
+///
+/// let mut currents = probe_plotter::make_metric_group!(CURRENTS: Currents).unwrap();
+/// currents.set(Currents { d: 0.5, q: 1.5 });
+/// currents.commit();
+/// ```
+///
+/// Will create the metrics `CURRENTS.d` and `CURRENTS.q` on the host side, which always show the
+/// values of the same commit.
+impl<T: Metricable> MetricGroup<T> {
+    /// Returns `None` if the group has already been taken
+    ///
+    /// # Safety
+    /// Internal use only by [make_metric_group]
+    pub unsafe fn take(x: *mut GroupStorage<T>) -> Option<Self> {
+        const {
+            assert!(core::mem::offset_of!(GroupStorage<T>, buffers) == 16);
+        }
+
+        let taken = unsafe { !crate::sync::take(&raw mut (*x).taken) };
+        (!taken).then_some(MetricGroup { x })
+    }
+
+    /// Index of the buffer which the host is not reading
+    fn back(&self) -> usize {
+        let commits = unsafe { (&raw const (*self.x).commits).read_volatile() };
+        (!commits & 1) as usize
+    }
+
+    /// Set the values of the next commit, the host does not see them before [Self::commit]
+    pub fn set(&mut self, x: T) {
+        let back = self.back();
+        unsafe { T::store(&raw mut (*self.x).buffers[back].0, x) }
+    }
+
+    /// Hand the values set since the last commit over to the host, timestamped with the current
+    /// time
+    ///
+    /// The values of the next commit start out as the ones before the last commit, so set all of
+    /// them before every commit.
+    pub fn commit(&mut self) {
+        let back = self.back();
+        unsafe {
+            let timestamp = crate::timestamp::now();
+            (&raw mut (*self.x).timestamps[back]).write_volatile(timestamp);
+
+            // The host must not see the new index before the values
+            fence(Ordering::Release);
+            let commits = (&raw const (*self.x).commits).read_volatile();
+            (&raw mut (*self.x).commits).write_volatile(commits.wrapping_add(1));
+        }
+    }
+}
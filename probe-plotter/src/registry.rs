@@ -0,0 +1,264 @@
+use core::{
+    cell::UnsafeCell,
+    marker::PhantomData,
+    sync::atomic::{Ordering, fence},
+};
+
+pub use macros::make_registry;
+
+use crate::{fixed::Fixed, metric::Metricable};
+
+/// Types which can be used as [DynamicMetric]s
+pub trait Registrable: Metricable {
+    /// Name of the type as understood by the host, padded with zeros
+    const TYPE_NAME: [u8; 8];
+}
+
+const fn type_name(name: &str) -> [u8; 8] {
+    let mut out = [0; 8];
+    let bytes = name.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        out[i] = bytes[i];
+        i += 1;
+    }
+    out
+}
+
+/// `Q15` for Q0.15 or `Q3_12` for Q3.12, same as in the macros
+const fn fixed_type_name(bits: u32, frac: u32) -> [u8; 8] {
+    let int = bits - 1 - frac;
+
+    let mut out = [0; 8];
+    out[0] = b'Q';
+    let mut len = 1;
+    if int > 0 {
+        len = push_number(&mut out, len, int);
+        out[len] = b'_';
+        len += 1;
+    }
+    push_number(&mut out, len, frac);
+    out
+}
+
+/// Append a number of at most two digits, returns the new length
+const fn push_number(out: &mut [u8; 8], mut len: usize, x: u32) -> usize {
+    if x >= 10 {
+        out[len] = b'0' + (x / 10) as u8;
+        len += 1;
+    }
+    out[len] = b'0' + (x % 10) as u8;
+    len + 1
+}
+
+macro_rules! impl_registrable {
+    ($($t:ident),*) => {$(
+        impl Registrable for $t {
+            const TYPE_NAME: [u8; 8] = type_name(stringify!($t));
+        }
+    )*};
+}
+
+impl_registrable!(i8, i16, i32, i64, u8, u16, u32, u64, f32, f64, bool);
+
+macro_rules! impl_registrable_fixed {
+    ($($t:ty),*) => {$(
+        impl<const FRAC: u32> Registrable for Fixed<$t, FRAC> {
+            const TYPE_NAME: [u8; 8] = fixed_type_name(<$t>::BITS, FRAC);
+        }
+    )*};
+}
+
+impl_registrable_fixed!(i8, i16, i32);
+
+/// One metric in a [Registry]
+///
+/// Read by the host, so the layout must not change without also changing probe-plotter-tools. The
+/// layout is the same regardless of the pointer width of the target, the alignment is forced to 8
+/// since `u64` is only 4 byte aligned on some 32 bit targets such as i686.
+#[derive(Clone, Copy)]
+#[repr(C, align(8))]
+struct Entry {
+    ty: [u8; 8],
+    name_len: u32,
+    index: u32,
+
+    /// Address of the name, 0 if the entry is unused
+    name: u64,
+
+    /// Storage of the value, large enough for any [Registrable]
+    value: [u64; 2],
+}
+
+impl Entry {
+    const UNUSED: Self = Entry {
+        ty: [0; 8],
+        name_len: 0,
+        index: 0,
+        name: 0,
+        value: [0; 2],
+    };
+}
+
+/// Storage of a [Registry]
+///
+/// Uses the same protocol as [crate::metric::Wide] to make sure the host never sees a half
+/// added or removed entry. The writer increments `start` before and sets `end` to the same value
+/// after modifying the entries. The host only reads all entries again when `end` has changed.
+#[repr(C)]
+struct RegistryStorage<const N: usize> {
+    end: u32,
+    entries: [Entry; N],
+    start: u32,
+}
+
+// Same as on the host, for every target
+const _: () = {
+    assert!(size_of::<Entry>() == 40);
+    assert!(core::mem::offset_of!(RegistryStorage<1>, entries) == 8);
+};
+
+/// Fixed capacity list of metrics which are added and removed at runtime
+///
+/// Metrics created by [crate::make_metric] must be known at link time. A registry instead allows
+/// driver instances created at runtime to publish metrics. The host periodically checks the
+/// registry for changes and adds or removes plots accordingly.
+///
+/// Create using [make_registry]
+///
+/// ```
+/// probe_plotter::make_registry!(REGISTRY: 16);
+///
+/// fn on_endpoint_enabled(ep: u8) {
+///     // Shown as `usb.ep.3` on the host side if `ep` is 3
+///     let mut packets = REGISTRY.register("usb.ep", ep.into(), 0u32).unwrap();
+///     packets.set(1);
+///     // The plot is removed when `packets` is dropped
+/// }
+/// ```
+pub struct Registry<const N: usize> {
+    storage: UnsafeCell<RegistryStorage<N>>,
+}
+
+// Safety: The entries are only modified within critical sections
+unsafe impl<const N: usize> Sync for Registry<N> {}
+
+impl<const N: usize> Registry<N> {
+    /// Internal use only by [make_registry]
+    pub const fn new() -> Self {
+        Registry {
+            storage: UnsafeCell::new(RegistryStorage {
+                end: 0,
+                entries: [Entry::UNUSED; N],
+                start: 0,
+            }),
+        }
+    }
+
+    /// Add a metric which on the host side will be called `name.index`
+    ///
+    /// Returns `None` if all entries are in use. The metric is removed again when the returned
+    /// [DynamicMetric] is dropped.
+    pub fn register<T: Registrable>(
+        &'static self,
+        name: &'static str,
+        index: u32,
+        initial: T,
+    ) -> Option<DynamicMetric<T>> {
+        const {
+            assert!(size_of::<T::Storage>() <= size_of::<[u64; 2]>());
+            assert!(align_of::<T::Storage>() <= align_of::<[u64; 2]>());
+        }
+
+        let storage = self.storage.get();
+        crate::sync::free(|| unsafe {
+            let end = &raw mut (*storage).end;
+            let start = &raw mut (*storage).start;
+            let entries = (&raw mut (*storage).entries).cast::<Entry>();
+
+            let entry = (0..N)
+                .map(|i| entries.add(i))
+                .find(|&entry| (&raw const (*entry).name).read_volatile() == 0)?;
+
+            modify(end, start, || {
+                (&raw mut (*entry).name_len).write_volatile(name.len() as u32);
+                (&raw mut (*entry).index).write_volatile(index);
+                (&raw mut (*entry).ty).write_volatile(T::TYPE_NAME);
+                T::store((&raw mut (*entry).value).cast(), initial);
+                (&raw mut (*entry).name).write_volatile(name.as_ptr() as usize as u64);
+            });
+
+            Some(DynamicMetric {
+                entry,
+                end,
+                start,
+                _t: PhantomData,
+            })
+        })
+    }
+}
+
+impl<const N: usize> Default for Registry<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// # Safety
+/// `end` and `start` must be the counters of a [RegistryStorage] and this must be called within a
+/// critical section
+unsafe fn modify(end: *mut u32, start: *mut u32, f: impl FnOnce()) {
+    unsafe {
+        let seq = start.read_volatile().wrapping_add(1);
+        start.write_volatile(seq);
+        fence(Ordering::Release);
+        f();
+        fence(Ordering::Release);
+        end.write_volatile(seq);
+    }
+}
+
+/// Metric added to a [Registry] at runtime, see [Registry::register]
+pub struct DynamicMetric<T: Registrable> {
+    entry: *mut Entry,
+    end: *mut u32,
+    start: *mut u32,
+    _t: PhantomData<T>,
+}
+
+// Safety: The entry is owned by us until we are dropped, the registry's counters are only
+// modified within critical sections
+unsafe impl<T> Send for DynamicMetric<T> where T: Send + Registrable {}
+
+// Safety: We only allow mutability through exclusive references so there is no risk
+// in having multiple shared references to this value across threads/execution contexts
+unsafe impl<T> Sync for DynamicMetric<T> where T: Sync + Registrable {}
+
+impl<T: Registrable> DynamicMetric<T> {
+    fn value(&self) -> *mut T::Storage {
+        unsafe { (&raw mut (*self.entry).value).cast() }
+    }
+
+    pub fn set(&mut self, x: T) {
+        unsafe { T::store(self.value(), x) }
+    }
+
+    pub fn get(&mut self) -> T {
+        // We are the only writer so the value is never torn
+        loop {
+            if let Ok(x) = unsafe { T::load(self.value()) } {
+                return x;
+            }
+        }
+    }
+}
+
+impl<T: Registrable> Drop for DynamicMetric<T> {
+    fn drop(&mut self) {
+        crate::sync::free(|| unsafe {
+            modify(self.end, self.start, || {
+                (&raw mut (*self.entry).name).write_volatile(0);
+            });
+        })
+    }
+}
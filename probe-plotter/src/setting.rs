@@ -1,9 +1,57 @@
-pub use macros::make_setting;
+use core::sync::atomic::{Ordering, fence};
+#[cfg(feature = "async")]
+use core::task::Poll;
 
-use crate::metric::Metricable;
+pub use macros::{make_setting, make_setting_from_address};
+
+use crate::metric::{LoadError, Metricable};
+
+/// Memory of a [Setting], shared with the host
+///
+/// To change the setting, the host writes the requested value to `pending` and then increments
+/// `generation`. The target validates the value and, if accepted, stores it in `value`. It then
+/// sets `rejected` and finally copies `generation` to `applied_generation` to let the host know the
+/// request has been handled.
+///
+/// Read by the host, so the layout must not change without also changing probe-plotter-tools.
+#[repr(C)]
+pub struct SettingStorage<T: Metricable> {
+    value: T::Storage,
+    pending: T::Storage,
+    generation: u32,
+    applied_generation: u32,
+    rejected: bool,
+}
+
+impl<T: Metricable> SettingStorage<T> {
+    pub const ZERO: Self = SettingStorage {
+        value: T::ZERO,
+        pending: T::ZERO,
+        generation: 0,
+        applied_generation: 0,
+        rejected: false,
+    };
+
+    /// # Safety
+    /// Internal use only by [make_setting], `this` must be valid for writes
+    pub unsafe fn store(this: *mut Self, x: T) {
+        unsafe { T::store(&raw mut (*this).value, x) }
+    }
+}
 
 pub struct Setting<T: Metricable> {
-    x: *mut T,
+    storage: *mut SettingStorage<T>,
+
+    /// Last `generation` which has been handled
+    seen_generation: u32,
+
+    /// Set when a new value is applied, cleared by [Setting::changed]
+    unseen_change: bool,
+
+    validate: Option<fn(T) -> Option<T>>,
+
+    /// Currently applied value
+    last: T,
 }
 
 // Safety: No one besides us and the debug probe has the raw pointer, so we can safely transfer
@@ -23,14 +71,269 @@ unsafe impl<T> Sync for Setting<T> where T: Sync + Metricable {}
 ///
 /// Will create a setting which on will show as a slider on the host side with the range
 /// 0..=10. The initial value will be 3.
+///
+/// Values written by the host are only applied when the setting is accessed using any of the
+/// methods below. Until then the host shows the new value as pending.
 impl<T: Metricable> Setting<T> {
     /// # Safety
     /// Internal use only by [make_setting]
-    pub const unsafe fn new(x: *mut T) -> Self {
-        Setting { x }
+    pub const unsafe fn new(storage: *mut SettingStorage<T>, initial: T) -> Self {
+        Setting {
+            storage,
+            seen_generation: 0,
+            unseen_change: false,
+            validate: None,
+            last: initial,
+        }
+    }
+
+    /// Check every value written by the host with `validate` before applying it
+    ///
+    /// `validate` returns the value to apply, which may differ from the requested one such as
+    /// when clamping, or `None` to reject the value. The host shows the outcome next to the
+    /// setting.
+    ///
+    /// ```
+    /// let mut speed = probe_plotter::make_setting!(SPEED: i32 = 0, -100..=100, 1)
+    ///     .unwrap()
+    ///     // Changing direction is not allowed while running
+    ///     .with_validation(|x| (x >= 0).then_some(x));
+    /// ```
+    pub fn with_validation(mut self, validate: fn(T) -> Option<T>) -> Self {
+        self.validate = Some(validate);
+        self
+    }
+
+    /// Handle a value written by the host, if any
+    fn update(&mut self) {
+        let storage = self.storage;
+        unsafe {
+            let generation = (&raw const (*storage).generation).read_volatile();
+            if generation == self.seen_generation {
+                return;
+            }
+            // The host writes the value before incrementing the generation
+            fence(Ordering::Acquire);
+
+            let applied = match T::load(&raw const (*storage).pending) {
+                Ok(requested) => match self.validate {
+                    Some(validate) => validate(requested),
+                    None => Some(requested),
+                },
+                // The host is in the middle of writing, try again next time
+                Err(LoadError::Torn) => return,
+                // Such as a discriminant which is not a variant of the enum
+                Err(LoadError::Invalid) => None,
+            };
+            if let Some(x) = applied {
+                T::store(&raw mut (*storage).value, x);
+                self.last = x;
+                self.unseen_change = true;
+            }
+
+            (&raw mut (*storage).rejected).write_volatile(applied.is_none());
+            fence(Ordering::Release);
+            (&raw mut (*storage).applied_generation).write_volatile(generation);
+            self.seen_generation = generation;
+        }
     }
 
     pub fn get(&mut self) -> T {
-        unsafe { self.x.read_volatile() }
+        self.update();
+        self.last
+    }
+
+    /// Returns `true` if a new value from the host has been applied since the last call
+    ///
+    /// The first call returns `false` unless the host has written the setting after it was
+    /// created. Rejected values are not considered a change.
+    pub fn changed(&mut self) -> bool {
+        self.update();
+        core::mem::take(&mut self.unseen_change)
+    }
+
+    /// Returns the value if the host has written a new value since the last call, see
+    /// [Setting::changed]
+    ///
+    /// ```
+    /// let mut cutoff = probe_plotter::make_setting!(CUTOFF: f32 = 100.0, 1.0..=1000.0, 1.0).unwrap();
+    /// loop {
+    ///     if let Some(cutoff) = cutoff.get_if_changed() {
+    ///         // Only recompute the filter coefficients when needed
+    ///     }
+    ///     # break;
+    /// }
+    /// ```
+    pub fn get_if_changed(&mut self) -> Option<T> {
+        self.changed().then(|| self.get())
+    }
+
+    /// Wait until a new value from the host has been applied, see [Setting::changed]
+    ///
+    /// The host can not wake the task by itself, so [poll_settings] has to be called periodically,
+    /// for example from a timer task, to check for changes.
+    ///
+    /// ```ignore
+    /// #[embassy_executor::task]
+    /// async fn settings_poller() {
+    ///     loop {
+    ///         embassy_time::Timer::after_millis(50).await;
+    ///         probe_plotter::poll_settings();
+    ///     }
+    /// }
+    ///
+    /// #[embassy_executor::task]
+    /// async fn filter() {
+    ///     let mut cutoff = probe_plotter::make_setting!(CUTOFF: f32 = 100.0, 1.0..=1000.0, 1.0).unwrap();
+    ///     loop {
+    ///         let cutoff = cutoff.wait_for_change().await;
+    ///         // Recompute the filter coefficients
+    ///     }
+    /// }
+    /// ```
+    #[cfg(feature = "async")]
+    pub async fn wait_for_change(&mut self) -> T {
+        core::future::poll_fn(|cx| {
+            if self.changed() {
+                return Poll::Ready(self.get());
+            }
+            waiting::register(cx.waker());
+            Poll::Pending
+        })
+        .await
+    }
+}
+
+#[cfg(feature = "async")]
+pub use waiting::poll_settings;
+
+#[cfg(feature = "async")]
+mod waiting {
+    use core::{cell::UnsafeCell, task::Waker};
+
+    /// Max number of tasks waiting in [super::Setting::wait_for_change] at the same time
+    const MAX_WAITING: usize = 8;
+
+    struct Wakers(UnsafeCell<[Option<Waker>; MAX_WAITING]>);
+
+    // Safety: Only accessed within critical sections
+    unsafe impl Sync for Wakers {}
+
+    static WAKERS: Wakers = Wakers(UnsafeCell::new([const { None }; MAX_WAITING]));
+
+    pub(super) fn register(waker: &Waker) {
+        crate::sync::free(|| {
+            let wakers = unsafe { &mut *WAKERS.0.get() };
+            if wakers.iter().flatten().any(|w| w.will_wake(waker)) {
+                return;
+            }
+            match wakers.iter_mut().find(|w| w.is_none()) {
+                Some(slot) => *slot = Some(waker.clone()),
+                // No room, have the task poll again as soon as possible rather than never
+                None => waker.wake_by_ref(),
+            }
+        })
+    }
+
+    /// Wake all tasks waiting in [super::Setting::wait_for_change] to check if their setting has
+    /// changed
+    ///
+    /// Call this periodically, the period decides how quickly a change made by the host is noticed.
+    pub fn poll_settings() {
+        let wakers = crate::sync::free(|| unsafe {
+            core::mem::replace(&mut *WAKERS.0.get(), [const { None }; MAX_WAITING])
+        });
+        for waker in wakers.into_iter().flatten() {
+            waker.wake();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    #[repr(u8)]
+    enum Mode {
+        Off = 0,
+        On = 1,
+    }
+
+    impl Metricable for Mode {
+        type Storage = u8;
+        const ZERO: u8 = 0;
+
+        unsafe fn store(storage: *mut u8, x: Self) {
+            unsafe { storage.write_volatile(x as u8) }
+        }
+
+        unsafe fn load(storage: *const u8) -> Result<Self, LoadError> {
+            match unsafe { storage.read_volatile() } {
+                0 => Ok(Mode::Off),
+                1 => Ok(Mode::On),
+                _ => Err(LoadError::Invalid),
+            }
+        }
+    }
+
+    /// Request a value the way the host does
+    fn request(storage: *mut SettingStorage<Mode>, x: u8) {
+        unsafe {
+            (*storage).pending = x;
+            (*storage).generation += 1;
+        }
+    }
+
+    /// `(value, rejected, applied_generation)` as read by the host
+    fn outcome(storage: *const SettingStorage<Mode>) -> (u8, bool, u32) {
+        unsafe {
+            (
+                (*storage).value,
+                (*storage).rejected,
+                (*storage).applied_generation,
+            )
+        }
+    }
+
+    #[test]
+    fn valid_value_is_applied() {
+        let mut storage = SettingStorage::<Mode>::ZERO;
+        let storage = &raw mut storage;
+        let mut setting = unsafe { Setting::new(storage, Mode::Off) };
+
+        request(storage, 1);
+        assert!(setting.changed());
+        assert_eq!(setting.get(), Mode::On);
+        assert_eq!(outcome(storage), (1, false, 1));
+    }
+
+    #[test]
+    fn invalid_discriminant_is_rejected() {
+        let mut storage = SettingStorage::<Mode>::ZERO;
+        let storage = &raw mut storage;
+        let mut setting = unsafe { Setting::new(storage, Mode::Off) };
+
+        request(storage, 7);
+        assert!(!setting.changed());
+        assert_eq!(setting.get(), Mode::Off);
+        assert_eq!(outcome(storage), (0, true, 1));
+
+        // The next valid request is applied and clears `rejected`
+        request(storage, 1);
+        assert_eq!(setting.get(), Mode::On);
+        assert_eq!(outcome(storage), (1, false, 2));
+    }
+
+    #[test]
+    fn value_failing_validation_is_rejected() {
+        let mut storage = SettingStorage::<Mode>::ZERO;
+        let storage = &raw mut storage;
+        let mut setting = unsafe { Setting::new(storage, Mode::Off) }
+            .with_validation(|x| (x == Mode::Off).then_some(x));
+
+        request(storage, 1);
+        assert_eq!(setting.get(), Mode::Off);
+        assert_eq!(outcome(storage), (0, true, 1));
     }
 }
@@ -0,0 +1,169 @@
+//! Samples sent to the host with [crate::Metric::push]
+//!
+//! The host reads metrics at its update rate, so values set with [crate::Metric::set] in between
+//! are never seen. [crate::Metric::push] also writes the value together with a timestamp into an
+//! RTT up channel which the host empties every update, so every sample is plotted at the time it
+//! was pushed.
+//!
+//! The channel has an RTT control block of its own, found by the host through its symbol, so it
+//! can be used next to the one of `defmt-rtt`. The channel is 1024 bytes by default, set the
+//! environment variable `PROBE_PLOTTER_STREAM_SIZE` when building to change that. Samples which do
+//! not fit are dropped and reported by the host.
+//!
+//! The timestamp is defined once by the application using [crate::timestamp]
+//!
+//! ```
+//! // The cycle counter of a Cortex-M running at 170 MHz, which has to be enabled first
+//! probe_plotter::timestamp!(170_000_000, cortex_m::peripheral::DWT::cycle_count());
+//!
+//! let mut current = probe_plotter::make_metric!(CURRENT: i16 = 0).unwrap();
+//! current.push(42);
+//! ```
+
+use core::{
+    cell::UnsafeCell,
+    sync::atomic::{Ordering, fence},
+};
+
+use crate::{fixed::Fixed, metric::Metricable};
+
+include!(concat!(env!("OUT_DIR"), "/stream_size.rs"));
+
+/// Size of the largest sample, the timestamp and id followed by a 64 bit value
+const MAX_SAMPLE_SIZE: usize = 16;
+
+const _: () = assert!(
+    STREAM_SIZE > MAX_SAMPLE_SIZE,
+    "PROBE_PLOTTER_STREAM_SIZE is too small to hold a sample"
+);
+
+/// Types which can be sent with [crate::Metric::push]
+pub trait Pushable: Metricable {
+    /// Write the value as little endian to the start of `out`, returns the number of bytes written
+    fn write_bytes(self, out: &mut [u8]) -> usize;
+}
+
+macro_rules! impl_pushable {
+    ($($t:ty),*) => {$(
+        impl Pushable for $t {
+            fn write_bytes(self, out: &mut [u8]) -> usize {
+                let bytes = self.to_le_bytes();
+                out[..bytes.len()].copy_from_slice(&bytes);
+                bytes.len()
+            }
+        }
+    )*};
+}
+
+impl_pushable!(i8, i16, i32, i64, u8, u16, u32, u64, f32, f64);
+
+impl Pushable for bool {
+    fn write_bytes(self, out: &mut [u8]) -> usize {
+        out[0] = self as u8;
+        1
+    }
+}
+
+macro_rules! impl_pushable_fixed {
+    ($($t:ty),*) => {$(
+        impl<const FRAC: u32> Pushable for Fixed<$t, FRAC> {
+            fn write_bytes(self, out: &mut [u8]) -> usize {
+                self.0.write_bytes(out)
+            }
+        }
+    )*};
+}
+
+impl_pushable_fixed!(i8, i16, i32);
+
+/// Up channel of an RTT control block, laid out like `SEGGER_RTT_BUFFER_UP`
+///
+/// Read by the host, which only writes `read`. Every sample is the timestamp and the id of the
+/// metric as `u32`s, followed by the value as written by [Pushable::write_bytes].
+#[repr(C)]
+struct UpChannel {
+    /// Nul terminated, the host finds the channel by it
+    name: *const u8,
+    buffer: *mut u8,
+    size: usize,
+
+    /// Offset in `buffer` of the next sample to write
+    write: usize,
+
+    /// Offset in `buffer` of the next sample for the host to read
+    read: usize,
+    flags: usize,
+}
+
+/// RTT control block with a single up channel, laid out like `SEGGER_RTT_CB`
+///
+/// `defmt-rtt` has a control block of its own, the host attaches to both.
+#[repr(C)]
+struct ControlBlock {
+    id: [u8; 16],
+    max_up_channels: i32,
+    max_down_channels: i32,
+    up: UpChannel,
+}
+
+/// Written by the target and read by the host at any time
+struct Shared<T>(UnsafeCell<T>);
+
+// Safety: Only modified within critical sections
+unsafe impl<T> Sync for Shared<T> {}
+
+static BUFFER: Shared<[u8; STREAM_SIZE]> = Shared(UnsafeCell::new([0; STREAM_SIZE]));
+
+#[unsafe(export_name = "probe_plotter.stream")]
+static CONTROL_BLOCK: Shared<ControlBlock> = Shared(UnsafeCell::new(ControlBlock {
+    id: *b"SEGGER RTT\0\0\0\0\0\0",
+    max_up_channels: 1,
+    max_down_channels: 0,
+    up: UpChannel {
+        name: c"probe-plotter".as_ptr().cast(),
+        buffer: BUFFER.0.get().cast(),
+        size: STREAM_SIZE,
+        write: 0,
+        read: 0,
+        // Skip samples which do not fit, `push` does so anyway
+        flags: 0,
+    },
+}));
+
+/// Number of samples which did not fit, not part of RTT
+#[unsafe(export_name = "probe_plotter.stream_dropped")]
+static DROPPED: Shared<u32> = Shared(UnsafeCell::new(0));
+
+/// Internal use only by [crate::Metric::push]
+///
+/// `id` is the address of the storage of the metric, which the host knows from the symbols
+pub(crate) fn push<T: Pushable>(id: u32, x: T) {
+    let mut sample = [0; MAX_SAMPLE_SIZE];
+    let len = 8 + x.write_bytes(&mut sample[8..]);
+
+    let channel = unsafe { &raw mut (*CONTROL_BLOCK.0.get()).up };
+    crate::sync::free(|| unsafe {
+        // Taken within the critical section so the samples are in order
+        let timestamp = crate::timestamp::now();
+        sample[..4].copy_from_slice(&timestamp.to_le_bytes());
+        sample[4..8].copy_from_slice(&id.to_le_bytes());
+
+        let write = (&raw const (*channel).write).read_volatile();
+        let read = (&raw const (*channel).read).read_volatile();
+        let free = (read + STREAM_SIZE - write - 1) % STREAM_SIZE;
+        if len > free {
+            let dropped = DROPPED.0.get();
+            dropped.write_volatile(dropped.read_volatile().wrapping_add(1));
+            return;
+        }
+
+        let data = BUFFER.0.get().cast::<u8>();
+        for (i, byte) in sample[..len].iter().enumerate() {
+            data.add((write + i) % STREAM_SIZE).write_volatile(*byte);
+        }
+
+        // The host must not see the new offset before the sample
+        fence(Ordering::Release);
+        (&raw mut (*channel).write).write_volatile((write + len) % STREAM_SIZE);
+    })
+}
@@ -0,0 +1,65 @@
+//! Internal use only by the macros and [crate::Registry]
+
+#[cfg(not(any(
+    feature = "cortex-m",
+    feature = "critical-section",
+    feature = "atomics",
+    feature = "std"
+)))]
+compile_error!("Enable one of the features `cortex-m`, `critical-section`, `atomics` or `std`");
+
+#[cfg(all(
+    feature = "async",
+    not(any(feature = "cortex-m", feature = "critical-section", feature = "std"))
+))]
+compile_error!("The feature `async` requires one of `cortex-m`, `critical-section` or `std`");
+
+/// Run `f` while holding a global lock
+#[cfg(feature = "std")]
+pub fn free<R>(f: impl FnOnce() -> R) -> R {
+    static LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+    // The lock only protects memory also accessed without it by the host, nothing to poison
+    let _guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    f()
+}
+
+/// Run `f` in a critical section
+#[cfg(all(feature = "cortex-m", not(feature = "std")))]
+pub fn free<R>(f: impl FnOnce() -> R) -> R {
+    cortex_m::interrupt::free(|_| f())
+}
+
+/// Run `f` in a critical section
+#[cfg(all(
+    feature = "critical-section",
+    not(feature = "cortex-m"),
+    not(feature = "std")
+))]
+pub fn free<R>(f: impl FnOnce() -> R) -> R {
+    critical_section::with(|_| f())
+}
+
+/// Set `flag`, returns `true` if it was not already set
+///
+/// # Safety
+/// `flag` must be valid for reads and writes and must only be accessed through this function
+#[cfg(any(feature = "atomics", feature = "std"))]
+pub unsafe fn take(flag: *mut bool) -> bool {
+    use core::sync::atomic::{AtomicBool, Ordering};
+
+    !unsafe { AtomicBool::from_ptr(flag) }.swap(true, Ordering::AcqRel)
+}
+
+/// Set `flag`, returns `true` if it was not already set
+///
+/// # Safety
+/// `flag` must be valid for reads and writes and must only be accessed through this function
+#[cfg(not(any(feature = "atomics", feature = "std")))]
+pub unsafe fn take(flag: *mut bool) -> bool {
+    free(|| unsafe {
+        let taken = flag.read_volatile();
+        flag.write_volatile(true);
+        !taken
+    })
+}
@@ -0,0 +1,14 @@
+//! Time of the samples sent with [crate::Metric::push] and of the commits of
+//! [crate::MetricGroup]s, defined once by the application using [timestamp]
+
+pub use macros::timestamp;
+
+unsafe extern "Rust" {
+    /// Defined by [timestamp]
+    fn _probe_plotter_timestamp() -> u32;
+}
+
+/// Current time in ticks, see [timestamp]
+pub(crate) fn now() -> u32 {
+    unsafe { _probe_plotter_timestamp() }
+}